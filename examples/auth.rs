@@ -5,19 +5,23 @@ use std::net::IpAddr;
 use std::path::Path;
 use tokio::io::{AsyncBufReadExt, BufReader};
 use tokio::sync::mpsc::channel;
-use tokio_watch::{Result, WatchedFile};
+use tokio_watch::WatchedFile;
 // use reqwest::Url;
 use std::io::Write;
 use std::sync::{Arc, Mutex};
 use tokio::sync::oneshot;
 
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
 #[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+#[allow(dead_code)]
 struct AbuseIpResponse {
-    ipAddress: IpAddr,
-    abuseConfidenceScore: u8,
-    countryCode: String,
-    totalReports: usize,
-    numDistinctUsers: usize,
+    ip_address: IpAddr,
+    abuse_confidence_score: u8,
+    country_code: String,
+    total_reports: usize,
+    num_distinct_users: usize,
 }
 
 #[derive(Deserialize, Debug)]
@@ -31,9 +35,11 @@ struct IpMessage {
     source: &'static str,
 }
 
+type IpMap = std::collections::hash_map::HashMap<IpAddr, (usize, Option<AbuseIpResponse>)>;
+
 #[derive(Clone)]
 struct IpDatabse {
-    db: Arc<Mutex<std::collections::hash_map::HashMap<IpAddr, (usize, Option<AbuseIpResponse>)>>>,
+    db: Arc<Mutex<IpMap>>,
     client: Client,
     key: String,
 }
@@ -76,7 +82,7 @@ impl IpDatabse {
         println!("got response {:?}", response);
         let mut db = self.db.lock().unwrap();
         db.get_mut(&ip).unwrap().1 = Some(response);
-        return 1;
+        1
     }
 
     fn to_csv(&self, path: impl AsRef<Path>) -> Result<()> {
@@ -93,10 +99,10 @@ impl IpDatabse {
                     "{:?},{},{},{},{},{}",
                     ip,
                     count,
-                    response.abuseConfidenceScore,
-                    response.countryCode,
-                    response.totalReports,
-                    response.numDistinctUsers
+                    response.abuse_confidence_score,
+                    response.country_code,
+                    response.total_reports,
+                    response.num_distinct_users
                 )?;
             }
         }
@@ -122,11 +128,11 @@ impl IpDatabse {
                 let total_reports = split.next().unwrap().parse()?;
                 let n_distinct = split.next().unwrap().parse()?;
                 let abuse_response = AbuseIpResponse {
-                    ipAddress: ip,
-                    abuseConfidenceScore: abuse_score,
-                    countryCode: country_code,
-                    totalReports: total_reports,
-                    numDistinctUsers: n_distinct,
+                    ip_address: ip,
+                    abuse_confidence_score: abuse_score,
+                    country_code,
+                    total_reports,
+                    num_distinct_users: n_distinct,
                 };
                 hash.insert(ip, (count, Some(abuse_response)));
             }
@@ -142,7 +148,7 @@ async fn main() -> Result<()> {
         tokio::sync::mpsc::Sender<IpMessage>,
         tokio::sync::mpsc::Receiver<IpMessage>,
     ) = channel(16);
-    let (shutdown_tx, mut shutdown_rx) = oneshot::channel::<()>();
+    let (_shutdown_tx, mut shutdown_rx) = oneshot::channel::<()>();
     let ip_handle = tokio::spawn(async move {
         loop {
             tokio::select! {
@@ -176,11 +182,12 @@ async fn main() -> Result<()> {
                                 None => user,
                             };
                             let ip = ip.parse().unwrap();
-                            tx2.send(IpMessage {
-                                ip,
-                                source: "auth.log",
-                            })
-                            .await;
+                            let _ = tx2
+                                .send(IpMessage {
+                                    ip,
+                                    source: "auth.log",
+                                })
+                                .await;
                             continue;
                         }
                     }
@@ -190,8 +197,7 @@ async fn main() -> Result<()> {
         }
     });
     let _mail = tokio::spawn(async move {
-        let mut file =
-            BufReader::new(WatchedFile::new("/var/log/mail.log").await.unwrap()).lines();
+        let mut file = BufReader::new(WatchedFile::new("/var/log/mail.log").await.unwrap()).lines();
         while let Some(line) = file.next_line().await.unwrap() {
             if line.contains(": SASL PLAIN authentication failed:")
                 || line.contains(": SASL LOGIN authentication failed:")
@@ -200,11 +206,12 @@ async fn main() -> Result<()> {
                 if let Some((_, identifier)) = line.split_once(": warning: unknown[") {
                     if let Some((ip, _)) = identifier.rsplit_once("]") {
                         let ip = ip.parse().unwrap();
-                        tx.send(IpMessage {
-                            ip,
-                            source: "mail.log.1",
-                        })
-                        .await;
+                        let _ = tx
+                            .send(IpMessage {
+                                ip,
+                                source: "mail.log.1",
+                            })
+                            .await;
                         continue;
                     }
                 }
@@ -215,7 +222,7 @@ async fn main() -> Result<()> {
 
     tokio::signal::ctrl_c().await?;
     println!("shutting down...");
-    
+
     let db = ip_handle.await?;
     db.to_csv("abuse_db_2.csv")?;
     Ok(())
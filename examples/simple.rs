@@ -1,8 +1,11 @@
+use futures_util::StreamExt;
 use std::io::SeekFrom;
 use std::path::Path;
 use tokio::fs::OpenOptions;
-use tokio::io::{AsyncBufReadExt, AsyncSeekExt, AsyncWriteExt, BufReader};
-use tokio_watch::{Result, WatchedFile};
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+use tokio_watch::WatchedFile;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
 
 async fn write_test_file(path: impl AsRef<Path>, append: bool) -> Result<()> {
     let mut file = OpenOptions::new()
@@ -52,13 +55,15 @@ async fn main() -> Result<()> {
     });
 
     let handle = tokio::spawn(async move {
-        let mut file = BufReader::new(file).lines();
-        while let Some(line) = file.next_line().await.unwrap() {
-            println!("{}", line);
+        let mut lines = file.lines();
+        while let Some(line) = lines.next().await {
+            let line = line.unwrap();
+            //the generation goes up when the file gets recreated or truncated.
+            println!("[{}:{}] {}", line.generation, line.byte_offset, line.text);
         }
     });
     // tokio::signal::ctrl_c().await?;
-    
+
     handle.await?;
     Ok(())
 }
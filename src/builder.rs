@@ -0,0 +1,314 @@
+use crate::compression;
+use crate::identity::Fingerprint;
+use crate::{recommended_watcher, NativeWatcher};
+use crate::{Checkpoint, Result, WatchHub, WatchSource, WatchedFile};
+use std::io::{ErrorKind, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+/// Where in the file we start reading when it already exists at build time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StartPosition {
+    /// From the first byte, the default.
+    Start,
+    /// Only what gets written from now on, like `tail -f -n 0`.
+    End,
+    /// A byte offset from the start of the file. Building fails with
+    /// [`InvalidInput`](std::io::ErrorKind::InvalidInput) if that's past its end.
+    Offset(u64),
+    /// The start of the last n lines, like `tail -n`. Found by reading backwards from the end, so
+    /// it's cheap on big files.
+    LastLines(usize),
+    /// The start of the first line within the last n bytes, so a long file can be picked up
+    /// without splitting a line or counting them. If none starts in there and the last line isn't
+    /// finished yet, from the start of that line.
+    LastBytes(u64),
+    /// Where a previous run left off, if it's still the same file. Otherwise from the start, the
+    /// file was rotated or truncated in the meantime.
+    Checkpoint(Checkpoint),
+}
+
+/// What to do once the file was deleted and we've read everything that was left in it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeletePolicy {
+    /// End the stream, like `tail -f`.
+    End,
+    /// Wait for a new file to be created at the same path and read that from the start.
+    WaitForRecreation,
+    /// Return an error from `poll_read`.
+    Error,
+}
+
+/// What to do when the file shrinks below the point we've read up to (or gets rewritten, see
+/// [`WatchedFileBuilder::compare_contents`]).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TruncatePolicy {
+    /// Quietly start over from the beginning of the file.
+    Rewind,
+    /// Return an error from every read after the truncation.
+    Error,
+    /// Return an error from the read that noticed the truncation, the next read starts over from the beginning.
+    Report,
+}
+
+/// How we find out about changes to the file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    /// The platform's native notifications (inotify, FSEvents, ReadDirectoryChangesW, ...)
+    Native,
+    /// Scan the directory every interval, for filesystems that don't deliver notifications.
+    Poll(Duration),
+    /// Native notifications, falling back to scanning every interval when they can't be set up,
+    /// like after running into the inotify watch limit.
+    ///
+    /// Some network and container filesystems accept a native watch but never report anything
+    /// on it, use [`Poll`](Backend::Poll) for those.
+    Auto(Duration),
+}
+
+/// Options for a [`WatchedFile`], created with [`WatchedFile::builder`].
+pub struct WatchedFileBuilder {
+    pub(crate) path: PathBuf,
+    pub(crate) start: StartPosition,
+    pub(crate) on_delete: DeletePolicy,
+    pub(crate) on_truncate: TruncatePolicy,
+    pub(crate) backend: Backend,
+    pub(crate) debounce: Option<Duration>,
+    pub(crate) wait_for_creation: bool,
+    pub(crate) compare_contents: bool,
+    pub(crate) source: Option<Box<dyn WatchSource>>,
+    pub(crate) read_rotated: bool,
+    pub(crate) follow: bool,
+    pub(crate) follow_rotation: bool,
+    pub(crate) native: NativeWatcher,
+}
+
+impl WatchedFileBuilder {
+    pub(crate) fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().into(),
+            start: StartPosition::Start,
+            on_delete: DeletePolicy::End,
+            on_truncate: TruncatePolicy::Rewind,
+            backend: Backend::Native,
+            debounce: None,
+            wait_for_creation: false,
+            compare_contents: false,
+            source: None,
+            read_rotated: false,
+            follow: true,
+            follow_rotation: true,
+            native: recommended_watcher,
+        }
+    }
+    /// Where to start reading a file that already exists, [`Start`](StartPosition::Start) by
+    /// default.
+    pub fn start(mut self, start: StartPosition) -> Self {
+        self.start = start;
+        self
+    }
+    /// What to do once the file was deleted, [`End`](DeletePolicy::End) by default.
+    pub fn on_delete(mut self, policy: DeletePolicy) -> Self {
+        self.on_delete = policy;
+        self
+    }
+    /// What to do when the file gets truncated, [`Rewind`](TruncatePolicy::Rewind) by default.
+    pub fn on_truncate(mut self, policy: TruncatePolicy) -> Self {
+        self.on_truncate = policy;
+        self
+    }
+    /// How to find out about changes, [`Native`](Backend::Native) by default.
+    pub fn backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+    /// After being woken up by a change, wait this long before reading so a burst of writes gets picked up in one go.
+    pub fn debounce(mut self, debounce: Duration) -> Self {
+        self.debounce = Some(debounce);
+        self
+    }
+    /// If nothing exists at the path yet, wait for it to be created instead of failing.
+    /// A file that shows up later is always read from the start.
+    pub fn wait_for_creation(mut self, wait: bool) -> Self {
+        self.wait_for_creation = wait;
+        self
+    }
+    /// Besides the file's identity and length, also compare the first kilobyte of it against what
+    /// we read whenever it changes. Catches a file that was truncated and written past its old
+    /// length in between two checks, at the cost of an extra read per check.
+    pub fn compare_contents(mut self, compare: bool) -> Self {
+        self.compare_contents = compare;
+        self
+    }
+    /// Get events through a watcher shared with other files instead of starting one of our own.
+    /// The hub's backend is used, `backend` is ignored.
+    pub fn hub(mut self, hub: &WatchHub) -> Self {
+        self.source = Some(Box::new(hub.clone()));
+        self
+    }
+    /// Hear about changes from `source` instead of starting a watcher of our own, `backend` is
+    /// ignored.
+    pub fn source(mut self, source: impl WatchSource + 'static) -> Self {
+        self.source = Some(Box::new(source));
+        self
+    }
+    /// First read the rotated copies of the file lying next to it, oldest first, then carry on
+    /// with the file itself from `start`. Both `auth.log.2`, `auth.log.1` and
+    /// `auth.log-20240101` style names are picked up. Copies compressed with gzip, zstd or xz are
+    /// decompressed with the `compression` feature and skipped without it.
+    ///
+    /// Every copy is a generation of its own, [`Line::path`](crate::Line::path) tells them apart.
+    pub fn read_rotated(mut self, read: bool) -> Self {
+        self.read_rotated = read;
+        self
+    }
+    /// With `false`, the first EOF ends the file like it would a plain [`File`], for going over a
+    /// file once with the same lines, offsets and checkpoints. No watcher is started unless we
+    /// have to wait for the file to be created.
+    ///
+    /// A file compressed with gzip, zstd or xz is decompressed like a rotated copy with the
+    /// `compression` feature, from the [`Start`](StartPosition::Start) only: line offsets are in the
+    /// decompressed data, there's no resuming in the middle of it. Without the feature it's read
+    /// as is, like any other file.
+    pub fn follow(mut self, follow: bool) -> Self {
+        self.follow = follow;
+        self
+    }
+    //with `false`, a rotated file ends once it's drained instead of carrying on with whatever
+    // replaced it, for `FileSet` to decide where to read that from.
+    pub(crate) fn follow_rotation(mut self, follow: bool) -> Self {
+        self.follow_rotation = follow;
+        self
+    }
+    //how to build a native watcher, for `testing` to make them fail.
+    #[cfg(feature = "testing")]
+    pub(crate) fn native_watcher(mut self, native: NativeWatcher) -> Self {
+        self.native = native;
+        self
+    }
+    pub async fn build(self) -> Result<WatchedFile> {
+        let mut file = match File::open(&self.path).await {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound && self.wait_for_creation => {
+                return WatchedFile::watch(self, None, false).await;
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if !self.follow && compression::is_compressed(&mut file).await? {
+            return self.build_compressed(file).await;
+        }
+        let offset = match self.start {
+            StartPosition::Start => 0,
+            StartPosition::End => file.seek(SeekFrom::End(0)).await?,
+            //further on we'd take the file for truncated once we get there.
+            StartPosition::Offset(offset) if offset > file.metadata().await?.len() => {
+                return Err(std::io::Error::new(
+                    ErrorKind::InvalidInput,
+                    "start offset past the end of the file",
+                )
+                .into());
+            }
+            StartPosition::Offset(offset) => offset,
+            StartPosition::LastLines(n) => last_lines_offset(&mut file, n).await?,
+            StartPosition::LastBytes(n) => last_bytes_offset(&mut file, n).await?,
+            StartPosition::Checkpoint(checkpoint) => checkpoint.resume_offset(&mut file).await?,
+        };
+        //we skip over the start of the file, so fingerprint it here for checkpoints.
+        let fingerprint = Fingerprint::of(&mut file, offset)
+            .await?
+            .unwrap_or_default();
+        file.seek(SeekFrom::Start(offset)).await?;
+        WatchedFile::watch(self, Some((file, offset, fingerprint)), false).await
+    }
+
+    //a compressed file doesn't grow, so we only get here without following it. it's read
+    // decompressed like a rotated copy, and the file itself is only there to end at.
+    async fn build_compressed(self, mut file: File) -> Result<WatchedFile> {
+        if !matches!(self.start, StartPosition::Start) {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                "a compressed file can only be read from the start",
+            )
+            .into());
+        }
+        let len = file.seek(SeekFrom::End(0)).await?;
+        let fingerprint = Fingerprint::of(&mut file, len).await?.unwrap_or_default();
+        file.seek(SeekFrom::Start(len)).await?;
+        WatchedFile::watch(self, Some((file, len, fingerprint)), true).await
+    }
+}
+
+//how much we read at once while looking for line starts.
+const CHUNK_SIZE: usize = 8 * 1024;
+
+//read backwards from the end a chunk at a time, until we've passed n newlines.
+async fn last_lines_offset(file: &mut File, n: usize) -> std::io::Result<u64> {
+    let len = file.seek(SeekFrom::End(0)).await?;
+    if n == 0 {
+        return Ok(len);
+    }
+    let mut buf = vec![0; CHUNK_SIZE];
+    let mut newlines = 0;
+    let mut end = len;
+    while end > 0 {
+        let start = end.saturating_sub(CHUNK_SIZE as u64);
+        let chunk = &mut buf[..(end - start) as usize];
+        file.seek(SeekFrom::Start(start)).await?;
+        file.read_exact(chunk).await?;
+        for (i, byte) in chunk.iter().enumerate().rev() {
+            let position = start + i as u64;
+            //a trailing newline ends the last line, it doesn't start another one.
+            if *byte == b'\n' && position + 1 != len {
+                newlines += 1;
+                if newlines == n {
+                    return Ok(position + 1);
+                }
+            }
+        }
+        end = start;
+    }
+    //fewer lines than that in the whole file.
+    Ok(0)
+}
+
+//where the line that `end` is in starts, reading backwards a chunk at a time.
+async fn line_start(file: &mut File, mut end: u64) -> std::io::Result<u64> {
+    let mut buf = vec![0; CHUNK_SIZE];
+    while end > 0 {
+        let start = end.saturating_sub(CHUNK_SIZE as u64);
+        let chunk = &mut buf[..(end - start) as usize];
+        file.seek(SeekFrom::Start(start)).await?;
+        file.read_exact(chunk).await?;
+        if let Some(newline) = chunk.iter().rposition(|b| *b == b'\n') {
+            return Ok(start + newline as u64 + 1);
+        }
+        end = start;
+    }
+    Ok(0)
+}
+
+//the first line that starts within the last n bytes, or the last line if it started before them
+// and isn't finished yet.
+async fn last_bytes_offset(file: &mut File, n: u64) -> std::io::Result<u64> {
+    let len = file.seek(SeekFrom::End(0)).await?;
+    if n >= len {
+        return Ok(0);
+    }
+    //start with the byte before, if that's a newline we're at the start of a line already.
+    let mut position = len - n - 1;
+    file.seek(SeekFrom::Start(position)).await?;
+    let mut buf = vec![0; CHUNK_SIZE];
+    loop {
+        let read = file.read(&mut buf).await?;
+        if read == 0 {
+            //the last line started before that and isn't finished yet, start with all of it.
+            return line_start(file, len - n - 1).await;
+        }
+        if let Some(newline) = buf[..read].iter().position(|b| *b == b'\n') {
+            return Ok(position + newline as u64 + 1);
+        }
+        position += read as u64;
+    }
+}
@@ -0,0 +1,88 @@
+use crate::identity::{FileId, Fingerprint};
+use std::fmt;
+use std::io::SeekFrom;
+use std::str::FromStr;
+use tokio::fs::File;
+use tokio::io::AsyncSeekExt;
+
+/// Where a [`WatchedFile`](crate::WatchedFile) was in which file, so a restarted process can pick
+/// up from there with [`StartPosition::Checkpoint`](crate::StartPosition::Checkpoint).
+///
+/// Converts to and from a short line of text with `to_string()` and `parse()` for storing it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Checkpoint {
+    pub offset: u64,
+    pub file_id: Option<FileId>,
+    pub fingerprint: Fingerprint,
+}
+
+impl Checkpoint {
+    //where to start reading `file` from, which is the saved offset if it's still the same file and 0 otherwise.
+    pub(crate) async fn resume_offset(&self, file: &mut File) -> std::io::Result<u64> {
+        let metadata = file.metadata().await?;
+        if let (Some(saved), Some(current)) = (self.file_id, FileId::from_metadata(&metadata)) {
+            if saved != current {
+                return Ok(0);
+            }
+        }
+        //inodes get reused, so the start of the file has to match too.
+        if Fingerprint::of(file, self.fingerprint.len).await? != Some(self.fingerprint) {
+            return Ok(0);
+        }
+        //the same file, but it got truncated while we were gone.
+        if metadata.len() < self.offset {
+            return Ok(0);
+        }
+        file.seek(SeekFrom::Start(self.offset)).await
+    }
+}
+
+impl fmt::Display for Checkpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ", self.offset)?;
+        match self.file_id {
+            Some(FileId { device, inode }) => write!(f, "{} {} ", device, inode)?,
+            None => write!(f, "- - ")?,
+        }
+        write!(f, "{} {:x}", self.fingerprint.len, self.fingerprint.hash)
+    }
+}
+
+/// The text didn't come from `Checkpoint::to_string`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseCheckpointError;
+
+impl fmt::Display for ParseCheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid checkpoint")
+    }
+}
+
+impl std::error::Error for ParseCheckpointError {}
+
+impl FromStr for Checkpoint {
+    type Err = ParseCheckpointError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        let [offset, device, inode, len, hash] = fields[..] else {
+            return Err(ParseCheckpointError);
+        };
+        let number = |field: &str| field.parse::<u64>().map_err(|_| ParseCheckpointError);
+        let file_id = match (device, inode) {
+            ("-", "-") => None,
+            (device, inode) => Some(FileId {
+                device: number(device)?,
+                inode: number(inode)?,
+            }),
+        };
+        Ok(Checkpoint {
+            offset: number(offset)?,
+            file_id,
+            fingerprint: Fingerprint {
+                len: number(len)?,
+                hash: u64::from_str_radix(hash, 16).map_err(|_| ParseCheckpointError)?,
+            },
+        })
+    }
+}
@@ -0,0 +1,85 @@
+//opening files that won't grow any more, decompressing them on the way with the `compression` feature.
+use std::path::Path;
+use tokio::fs::File;
+use tokio::io::AsyncRead;
+
+pub(crate) type Reader = Box<dyn AsyncRead + Send + Unpin>;
+
+//extensions of compressed copies we can read, the format itself is told by its magic bytes.
+#[cfg(feature = "compression")]
+pub(crate) const SUPPORTED: &[&str] = &["gz", "zst", "xz"];
+#[cfg(not(feature = "compression"))]
+pub(crate) const SUPPORTED: &[&str] = &[];
+
+#[cfg(feature = "compression")]
+const GZIP: &[u8] = &[0x1f, 0x8b];
+#[cfg(feature = "compression")]
+const ZSTD: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+#[cfg(feature = "compression")]
+const XZ: &[u8] = &[0xfd, b'7', b'z', b'X', b'Z', 0x00];
+
+//without the feature nothing is compressed, the bytes are handed out as they are.
+#[cfg(not(feature = "compression"))]
+pub(crate) async fn is_compressed(_file: &mut File) -> std::io::Result<bool> {
+    Ok(false)
+}
+
+//whether `file` starts like something `open` would decompress. the file is left at its start.
+#[cfg(feature = "compression")]
+pub(crate) async fn is_compressed(file: &mut File) -> std::io::Result<bool> {
+    use std::io::SeekFrom;
+    use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+    let mut magic = [0; 6];
+    let mut filled = 0;
+    file.seek(SeekFrom::Start(0)).await?;
+    while filled < magic.len() {
+        match file.read(&mut magic[filled..]).await? {
+            0 => break,
+            read => filled += read,
+        }
+    }
+    file.seek(SeekFrom::Start(0)).await?;
+    let magic = &magic[..filled];
+    Ok([GZIP, ZSTD, XZ]
+        .iter()
+        .any(|format| magic.starts_with(format)))
+}
+
+//`path` as it was before it got compressed, or as is if it wasn't.
+pub(crate) async fn open(path: &Path) -> std::io::Result<Reader> {
+    decode(File::open(path).await?).await
+}
+
+#[cfg(not(feature = "compression"))]
+async fn decode(file: File) -> std::io::Result<Reader> {
+    Ok(Box::new(file))
+}
+
+#[cfg(feature = "compression")]
+async fn decode(file: File) -> std::io::Result<Reader> {
+    use async_compression::tokio::bufread::{GzipDecoder, XzDecoder, ZstdDecoder};
+    use tokio::io::{AsyncBufReadExt, BufReader};
+
+    let mut reader = BufReader::new(file);
+    let magic = reader.fill_buf().await?;
+    Ok(if magic.starts_with(GZIP) {
+        debug!("reading gzip compressed file");
+        let mut decoder = GzipDecoder::new(reader);
+        //`gzip` appending to a file leaves one member after the other.
+        decoder.multiple_members(true);
+        Box::new(decoder)
+    } else if magic.starts_with(ZSTD) {
+        debug!("reading zstd compressed file");
+        let mut decoder = ZstdDecoder::new(reader);
+        decoder.multiple_members(true);
+        Box::new(decoder)
+    } else if magic.starts_with(XZ) {
+        debug!("reading xz compressed file");
+        let mut decoder = XzDecoder::new(reader);
+        decoder.multiple_members(true);
+        Box::new(decoder)
+    } else {
+        Box::new(reader)
+    })
+}
@@ -0,0 +1,99 @@
+use std::fmt;
+use std::io;
+
+/// Everything that can go wrong while watching a file.
+///
+/// `poll_read` can only return an [`io::Error`], errors coming from there wrap one of these and can
+/// be turned back into it with `Error::from`.
+///
+/// `Io` and `Watch` don't repeat the error they wrap in their message, it is returned by
+/// [`source`](std::error::Error::source) instead.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    Io(io::Error),
+    /// The file watcher failed, we might have missed changes to the file.
+    Watch(notify::Error),
+    /// The file was deleted and the [`DeletePolicy`](crate::DeletePolicy) says that's an error.
+    FileVanished,
+    /// A new file showed up at our path but we aren't allowed to read it.
+    PermissionChanged,
+    /// The file shrank below what we had already read, or was rewritten from the start when
+    /// [`compare_contents`](crate::WatchedFileBuilder::compare_contents) is set.
+    Truncated {
+        old_len: u64,
+        new_len: u64,
+    },
+}
+
+impl Error {
+    /// Whether it makes sense to keep reading (or to build a new `WatchedFile`) after this error.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::Io(e) => matches!(
+                e.kind(),
+                io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+            ),
+            Error::Watch(e) => matches!(
+                e.kind,
+                notify::ErrorKind::Generic(_) | notify::ErrorKind::Io(_)
+            ),
+            Error::Truncated { .. } => true,
+            Error::FileVanished | Error::PermissionChanged => false,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(_) => write!(f, "io error"),
+            Error::Watch(_) => write!(f, "file watcher failed"),
+            Error::FileVanished => write!(f, "watched file was deleted"),
+            Error::PermissionChanged => write!(f, "no permission to read the watched file anymore"),
+            Error::Truncated { old_len, new_len } => write!(
+                f,
+                "watched file was truncated from {} to {} bytes",
+                old_len, new_len
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Watch(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    //unwrap errors that went through `poll_read` back into what they were.
+    fn from(e: io::Error) -> Self {
+        if e.get_ref().is_some_and(|inner| inner.is::<Error>()) {
+            *e.into_inner().unwrap().downcast::<Error>().unwrap()
+        } else {
+            Error::Io(e)
+        }
+    }
+}
+
+impl From<notify::Error> for Error {
+    fn from(e: notify::Error) -> Self {
+        Error::Watch(e)
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Io(e) => e,
+            Error::FileVanished => io::Error::new(io::ErrorKind::NotFound, e),
+            Error::PermissionChanged => io::Error::new(io::ErrorKind::PermissionDenied, e),
+            Error::Truncated { .. } | Error::Watch(_) => io::Error::other(e),
+        }
+    }
+}
@@ -0,0 +1,18 @@
+/// Something that happened to the watched file, as seen from the byte stream.
+///
+/// Events are sent at the point in the stream where they take effect: `Rotated` and `Recreated`
+/// right before the first byte of the new file is returned, `Truncated` right before reading starts
+/// over, and `Deleted` once everything left in the deleted file has been read.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileEvent {
+    /// The file shrank below what we had already read, or was rewritten from the start when
+    /// [`compare_contents`](crate::WatchedFileBuilder::compare_contents) is set.
+    Truncated { old_len: u64, new_len: u64 },
+    /// The file was renamed away (or something was renamed onto its path) and we moved on to the
+    /// file that lives at the path now.
+    Rotated,
+    /// The file was deleted and a new one got created at the same path.
+    Recreated,
+    /// The file was deleted.
+    Deleted,
+}
@@ -0,0 +1,173 @@
+use crate::{Checkpoint, Error, FileId, Line, Lines, Result, StartPosition, WatchHub, WatchedFile};
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use futures_core::Stream;
+use std::collections::{HashSet, VecDeque};
+use std::fs::Metadata;
+use std::future::Future;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+type IdentifyFuture =
+    Pin<Box<dyn Future<Output = (PathBuf, StartPosition, std::io::Result<Metadata>)> + Send>>;
+type OpenFuture = Pin<Box<dyn Future<Output = (PathBuf, Result<WatchedFile>)> + Send>>;
+
+//how many files we remember where we left, most of them got rotated to a name we don't follow.
+const FINISHED_CAPACITY: usize = 1024;
+
+//the files a `WatchedGlob` or `WatchedDir` is following, merged into one stream of lines.
+pub(crate) struct FileSet {
+    //one watcher for all of them.
+    hub: WatchHub,
+    //paths we're following or opening, so we don't pick a path up twice.
+    known: HashSet<PathBuf>,
+    files: Vec<(PathBuf, Lines)>,
+    //paths we're looking up the file behind, to tell if it's one we already know under another name.
+    identifying: Vec<IdentifyFuture>,
+    opening: Vec<(Option<FileId>, OpenFuture)>,
+    //where we left files that ended, newest last, to carry on from there if they show up under a new
+    // name. the checkpoint also checks the start of the file, inodes get reused.
+    finished: VecDeque<(FileId, Checkpoint)>,
+    //where to start polling `files` next, so a busy file can't starve the others.
+    next: usize,
+}
+
+impl FileSet {
+    pub(crate) fn new(hub: WatchHub) -> Self {
+        Self {
+            hub,
+            known: HashSet::new(),
+            files: Vec::new(),
+            identifying: Vec::new(),
+            opening: Vec::new(),
+            finished: VecDeque::new(),
+            next: 0,
+        }
+    }
+
+    //start following `path`, unless we already are.
+    pub(crate) fn open(&mut self, path: PathBuf, start: StartPosition) {
+        if self.known.insert(path.clone()) {
+            self.identifying.push(Box::pin(async move {
+                let metadata = tokio::fs::metadata(&path).await;
+                (path, start, metadata)
+            }));
+        }
+    }
+
+    //whether one of our files is `id` already, under whatever name.
+    fn following(&self, id: Option<FileId>) -> bool {
+        id.is_some()
+            && (self
+                .files
+                .iter()
+                .any(|(_, lines)| lines.get_ref().file_id() == id)
+                || self.opening.iter().any(|(opening, _)| *opening == id))
+    }
+
+    //the next line from any of the files. files that end or fail are dropped, so this never returns `Ready(None)`.
+    pub(crate) fn poll_next(&mut self, cx: &mut Context<'_>) -> Poll<Result<(PathBuf, Line)>> {
+        let mut i = 0;
+        while i < self.identifying.len() {
+            match self.identifying[i].as_mut().poll(cx) {
+                Poll::Pending => i += 1,
+                Poll::Ready((path, start, result)) => {
+                    drop(self.identifying.swap_remove(i));
+                    let id = match result {
+                        Ok(metadata) if metadata.is_file() => FileId::from_metadata(&metadata),
+                        //a directory or something else we can't read lines from.
+                        Ok(_) => {
+                            self.known.remove(&path);
+                            continue;
+                        }
+                        //gone again before we got to it.
+                        Err(e) if e.kind() == ErrorKind::NotFound => {
+                            self.known.remove(&path);
+                            continue;
+                        }
+                        Err(e) => {
+                            self.known.remove(&path);
+                            return Poll::Ready(Err(e.into()));
+                        }
+                    };
+                    if self.following(id) {
+                        //renamed while we're still reading it, that's where its lines come from.
+                        debug!(path = %path.display(), "already following this file under another name");
+                        self.known.remove(&path);
+                        continue;
+                    }
+                    //rotated away after we read it, only what was written since is new.
+                    let finished = self
+                        .finished
+                        .iter()
+                        .position(|(finished, _)| Some(*finished) == id);
+                    let start = match finished.and_then(|i| self.finished.remove(i)) {
+                        Some((_, checkpoint)) => StartPosition::Checkpoint(checkpoint),
+                        None => start,
+                    };
+                    let hub = self.hub.clone();
+                    self.opening.push((
+                        id,
+                        Box::pin(async move {
+                            let file = WatchedFile::builder(&path)
+                                .start(start)
+                                .hub(&hub)
+                                .follow_rotation(false)
+                                .build()
+                                .await;
+                            (path, file)
+                        }),
+                    ));
+                }
+            }
+        }
+        let mut i = 0;
+        while i < self.opening.len() {
+            match self.opening[i].1.as_mut().poll(cx) {
+                Poll::Pending => i += 1,
+                Poll::Ready((path, result)) => {
+                    drop(self.opening.swap_remove(i));
+                    match result {
+                        Ok(file) => self.files.push((path, file.lines())),
+                        //gone again before we got to it.
+                        Err(Error::Io(e)) if e.kind() == ErrorKind::NotFound => {
+                            self.known.remove(&path);
+                        }
+                        Err(e) => {
+                            self.known.remove(&path);
+                            return Poll::Ready(Err(e));
+                        }
+                    }
+                }
+            }
+        }
+        for _ in 0..self.files.len() {
+            let i = self.next % self.files.len();
+            self.next = i + 1;
+            let (path, lines) = &mut self.files[i];
+            match Pin::new(lines).poll_next(cx) {
+                Poll::Pending => {}
+                Poll::Ready(Some(Ok(line))) => return Poll::Ready(Ok((path.clone(), line))),
+                Poll::Ready(result) => {
+                    let (path, lines) = self.files.swap_remove(i);
+                    self.known.remove(&path);
+                    if let Some(Err(e)) = result {
+                        return Poll::Ready(Err(e));
+                    }
+                    let file = lines.into_inner();
+                    if let Some(id) = file.file_id() {
+                        if self.finished.len() == FINISHED_CAPACITY {
+                            self.finished.pop_front();
+                        }
+                        self.finished.push_back((id, file.checkpoint()));
+                    }
+                    //deleted or rotated away and read to the end, something new might be there already.
+                    self.open(path, StartPosition::Start);
+                    cx.waker().wake_by_ref();
+                    return Poll::Pending;
+                }
+            }
+        }
+        Poll::Pending
+    }
+}
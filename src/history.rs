@@ -0,0 +1,191 @@
+use crate::compression::{self, Reader};
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use std::collections::VecDeque;
+use std::future::Future;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncRead, ReadBuf};
+
+type OpenFuture = Pin<Box<dyn Future<Output = std::io::Result<Reader>> + Send>>;
+
+//extensions logrotate gives the copies it compressed.
+const COMPRESSED: &[&str] = &["gz", "bz2", "xz", "zst", "lz4", "z", "Z"];
+
+//the rotated copies of a file, read one after the other before we get to the file itself.
+pub(crate) struct History {
+    //still to be read, oldest first.
+    pending: VecDeque<PathBuf>,
+    current: Option<Segment>,
+    opening: Option<(PathBuf, OpenFuture)>,
+    //whether we got to read anything yet, only the copies after the first one start a new generation.
+    started: bool,
+    //we opened the next copy but didn't hand out anything from it yet.
+    next_segment: bool,
+}
+
+struct Segment {
+    path: PathBuf,
+    reader: Reader,
+    offset: u64,
+}
+
+pub(crate) enum Progress {
+    //something was read into the buffer, from a different copy than last time if `next_segment` is set.
+    Read { next_segment: bool },
+    //every copy was read to the end, nothing was read this time.
+    Done { started: bool },
+}
+
+impl History {
+    pub(crate) fn new(pending: VecDeque<PathBuf>) -> Self {
+        Self {
+            pending,
+            current: None,
+            opening: None,
+            started: false,
+            next_segment: false,
+        }
+    }
+
+    pub(crate) fn started(&self) -> bool {
+        self.started
+    }
+
+    //where we are in the copy we're reading.
+    pub(crate) fn offset(&self) -> u64 {
+        self.current.as_ref().map_or(0, |segment| segment.offset)
+    }
+
+    //the copy we're reading or are about to.
+    pub(crate) fn path(&self) -> Option<&Path> {
+        match (&self.current, &self.opening) {
+            (Some(segment), _) => Some(&segment.path),
+            (None, Some((path, _))) => Some(path),
+            (None, None) => self.pending.front().map(PathBuf::as_path),
+        }
+    }
+
+    pub(crate) fn poll_read(
+        &mut self,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<Progress>> {
+        loop {
+            if let Some(segment) = &mut self.current {
+                let before = buf.filled().len();
+                if let Err(e) = std::task::ready!(Pin::new(&mut segment.reader).poll_read(cx, buf))
+                {
+                    //not much we can do about a broken copy, move on to the next one.
+                    self.current = None;
+                    return Poll::Ready(Err(e));
+                }
+                let read = buf.filled().len() - before;
+                if read != 0 {
+                    segment.offset += read as u64;
+                    return Poll::Ready(Ok(Progress::Read {
+                        next_segment: std::mem::take(&mut self.next_segment),
+                    }));
+                }
+                debug!(path = %segment.path.display(), "finished rotated copy");
+                self.current = None;
+            }
+            if self.opening.is_none() {
+                let Some(path) = self.pending.pop_front() else {
+                    return Poll::Ready(Ok(Progress::Done {
+                        started: self.started,
+                    }));
+                };
+                let segment = path.clone();
+                let open = Box::pin(async move { compression::open(&segment).await });
+                self.opening = Some((path, open));
+            }
+            let (_, open) = self.opening.as_mut().unwrap();
+            let result = std::task::ready!(open.as_mut().poll(cx));
+            let (path, _) = self.opening.take().unwrap();
+            match result {
+                Ok(reader) => {
+                    self.next_segment = self.started;
+                    self.started = true;
+                    self.current = Some(Segment {
+                        path,
+                        reader,
+                        offset: 0,
+                    });
+                }
+                //rotated on again before we got to it, whatever it became is further down the list or gone.
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => return Poll::Ready(Err(e)),
+            }
+        }
+    }
+}
+
+//how a rotated copy is told apart from the others, sorting oldest first.
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum Suffix {
+    //`name.3` is older than `name.1`.
+    Numbered(std::cmp::Reverse<u64>),
+    //`name-20240102` is newer than `name-20240101`.
+    Dated(String),
+}
+
+impl Suffix {
+    //the suffix of a rotated copy of `file_name`, and whether it's compressed in a way we can't read.
+    fn parse(name: &str, file_name: &str) -> Option<(Self, bool)> {
+        let rest = name.strip_prefix(file_name)?;
+        let (numbered, rest) = match rest.strip_prefix('.') {
+            Some(rest) => (true, rest),
+            None => (false, rest.strip_prefix('-')?),
+        };
+        let (suffix, extension) = match rest.split_once('.') {
+            Some((suffix, extension)) => (suffix, Some(extension)),
+            None => (rest, None),
+        };
+        if extension.is_some_and(|extension| !COMPRESSED.contains(&extension)) {
+            return None;
+        }
+        if suffix.is_empty() || !suffix.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let suffix = if numbered {
+            Suffix::Numbered(std::cmp::Reverse(suffix.parse().ok()?))
+        } else {
+            Suffix::Dated(suffix.to_string())
+        };
+        let unsupported =
+            extension.is_some_and(|extension| !compression::SUPPORTED.contains(&extension));
+        Some((suffix, unsupported))
+    }
+}
+
+//the rotated copies next to `path`, like `auth.log.2` and `auth.log.1` or `auth.log-20240101`, oldest first.
+pub(crate) async fn find_rotated(path: &Path) -> std::io::Result<VecDeque<PathBuf>> {
+    let Some(file_name) = path.file_name().and_then(|name| name.to_str()) else {
+        return Ok(VecDeque::new());
+    };
+    let dir = match path.parent() {
+        Some(parent) if parent != Path::new("") => parent,
+        _ => Path::new("."),
+    };
+    let mut rotated = Vec::new();
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name();
+        let Some((suffix, unsupported)) = name
+            .to_str()
+            .and_then(|name| Suffix::parse(name, file_name))
+        else {
+            continue;
+        };
+        if unsupported {
+            debug!(path = %entry.path().display(), "skipping compressed rotated copy");
+            continue;
+        }
+        if entry.file_type().await?.is_file() {
+            rotated.push((suffix, dir.join(&name)));
+        }
+    }
+    rotated.sort();
+    Ok(rotated.into_iter().map(|(_, path)| path).collect())
+}
@@ -0,0 +1,124 @@
+use crate::source::{EventCallback, Router, WatchGuard, WatchSource};
+use crate::{falls_back, new_watcher, recommended_watcher, Backend, NativeWatcher, Result};
+use notify::{Event, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// A single notify watcher shared by many [`WatchedFile`](crate::WatchedFile)s, so tailing
+/// hundreds of files doesn't take hundreds of inotify instances and threads.
+///
+/// Hand it to [`WatchedFileBuilder::hub`](crate::WatchedFileBuilder::hub). Directories are watched
+/// as long as at least one file in them is, cloning the hub is cheap and shares the watcher.
+#[derive(Clone)]
+pub struct WatchHub {
+    backend: Backend,
+    //registering and unregistering goes through here, one at a time.
+    watcher: Arc<Mutex<Watchers>>,
+    //only ever locked on its own: the watcher's thread locks it to route events while
+    // (un)watching waits on that same thread.
+    router: Arc<Mutex<Router>>,
+}
+
+struct Watchers {
+    main: Box<dyn Watcher + Send>,
+    //with `Backend::Auto`, polls the directories `main` couldn't take.
+    fallback: Option<Box<dyn Watcher + Send>>,
+    polled: HashSet<PathBuf>,
+}
+
+impl WatchHub {
+    /// A hub using the platform's native notifications.
+    pub fn new() -> Result<Self> {
+        Self::with_backend(Backend::Native)
+    }
+
+    /// A hub getting its events from `backend`.
+    pub fn with_backend(backend: Backend) -> Result<Self> {
+        Self::with_native_watcher(backend, recommended_watcher)
+    }
+
+    //like `with_backend`, building native watchers with `native`.
+    pub(crate) fn with_native_watcher(backend: Backend, native: NativeWatcher) -> Result<Self> {
+        let router = Arc::new(Mutex::new(Router::default()));
+        let main = new_watcher(backend, native, forward(&router))?;
+        Ok(Self {
+            backend,
+            watcher: Arc::new(Mutex::new(Watchers {
+                main,
+                fallback: None,
+                polled: HashSet::new(),
+            })),
+            router,
+        })
+    }
+
+    fn watch_dir(&self, watchers: &mut Watchers, dir: &Path) -> Result<()> {
+        let e = match watchers.main.watch(dir, RecursiveMode::NonRecursive) {
+            Ok(()) => return Ok(()),
+            Err(e) => e,
+        };
+        let Backend::Auto(poll_interval) = self.backend else {
+            return Err(e.into());
+        };
+        if !falls_back(&e) {
+            return Err(e.into());
+        }
+        warn!(error = %e, dir = %dir.display(), "native watcher failed, polling instead");
+        let fallback = match &mut watchers.fallback {
+            Some(fallback) => fallback,
+            None => watchers.fallback.insert(new_watcher(
+                Backend::Poll(poll_interval),
+                recommended_watcher,
+                forward(&self.router),
+            )?),
+        };
+        fallback.watch(dir, RecursiveMode::NonRecursive)?;
+        watchers.polled.insert(dir.to_path_buf());
+        Ok(())
+    }
+}
+
+impl WatchSource for WatchHub {
+    fn watch(&self, dir: &Path, callback: EventCallback) -> Result<WatchGuard> {
+        let mut watcher = self.watcher.lock().unwrap();
+        let (id, first) = self.router.lock().unwrap().add(dir, callback);
+        if first {
+            if let Err(e) = self.watch_dir(&mut watcher, dir) {
+                self.router.lock().unwrap().remove(dir, id);
+                return Err(e);
+            }
+        }
+        Ok(WatchGuard::new(Registration {
+            hub: self.clone(),
+            dir: dir.to_path_buf(),
+            id,
+        }))
+    }
+}
+
+fn forward(router: &Arc<Mutex<Router>>) -> impl FnMut(notify::Result<Event>) + Send + 'static {
+    let router = router.clone();
+    move |res| router.lock().unwrap().route(res)
+}
+
+//keeps a callback registered with a `WatchHub`, unwatching the directory after the last one goes.
+struct Registration {
+    hub: WatchHub,
+    dir: PathBuf,
+    id: u64,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        let mut watcher = self.hub.watcher.lock().unwrap();
+        let last = self.hub.router.lock().unwrap().remove(&self.dir, self.id);
+        if last {
+            let watchers = &mut *watcher;
+            let _ = match &mut watchers.fallback {
+                Some(fallback) if watchers.polled.remove(&self.dir) => fallback.unwatch(&self.dir),
+                _ => watchers.main.unwatch(&self.dir),
+            };
+        }
+    }
+}
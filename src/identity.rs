@@ -0,0 +1,81 @@
+use std::io::SeekFrom;
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+/// The device and inode of a file, which stay the same across renames but not across recreation.
+///
+/// Only available on unix, elsewhere we have to rely on the [`Fingerprint`] alone.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct FileId {
+    pub device: u64,
+    pub inode: u64,
+}
+
+impl FileId {
+    #[cfg(unix)]
+    pub fn from_metadata(metadata: &std::fs::Metadata) -> Option<Self> {
+        use std::os::unix::fs::MetadataExt;
+        Some(FileId {
+            device: metadata.dev(),
+            inode: metadata.ino(),
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub fn from_metadata(_metadata: &std::fs::Metadata) -> Option<Self> {
+        None
+    }
+}
+
+//how much of the start of a file goes into its fingerprint.
+pub(crate) const FINGERPRINT_LEN: u64 = 1024;
+
+/// A hash of the first (up to 1024) bytes of a file, to recognize it when the [`FileId`] isn't
+/// enough (inodes get reused) or isn't available.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Fingerprint {
+    /// How many bytes went into `hash`.
+    pub len: u64,
+    pub hash: u64,
+}
+
+//64 bit FNV-1a, we need something that doesn't change between runs or compiler versions.
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+impl Default for Fingerprint {
+    fn default() -> Self {
+        Fingerprint {
+            len: 0,
+            hash: FNV_OFFSET,
+        }
+    }
+}
+
+impl Fingerprint {
+    //feed bytes that follow the ones already hashed, anything past FINGERPRINT_LEN is ignored.
+    pub(crate) fn update(&mut self, bytes: &[u8]) {
+        let take = (FINGERPRINT_LEN.saturating_sub(self.len) as usize).min(bytes.len());
+        for byte in &bytes[..take] {
+            self.hash ^= *byte as u64;
+            self.hash = self.hash.wrapping_mul(FNV_PRIME);
+        }
+        self.len += take as u64;
+    }
+
+    /// Fingerprint the first `len` bytes of `file` (capped at 1024), leaving the file positioned
+    /// right after them. Returns `None` if the file is shorter than that.
+    pub(crate) async fn of(file: &mut File, len: u64) -> std::io::Result<Option<Self>> {
+        let len = len.min(FINGERPRINT_LEN);
+        let mut buf = vec![0; len as usize];
+        file.seek(SeekFrom::Start(0)).await?;
+        match file.read_exact(&mut buf).await {
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        }
+        let mut fingerprint = Fingerprint::default();
+        fingerprint.update(&buf);
+        Ok(Some(fingerprint))
+    }
+}
@@ -1,38 +1,278 @@
+//! Async file tailing on top of tokio and notify, following files through rotation, truncation
+//! and deletion.
+//!
+//! This crate contains no `unsafe` code, `#![forbid(unsafe_code)]` makes sure it stays that way.
+#![forbid(unsafe_code)]
+
 use core::pin::Pin;
 use core::task::{Context, Poll};
+use history::{History, Progress};
 use notify::event::ModifyKind;
-use notify::{Config, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
-use std::borrow::BorrowMut;
+use notify::poll::PollWatcherConfig;
+use notify::{Config, Event, EventHandler, EventKind, PollWatcher, RecursiveMode, Watcher};
+use queue::EventQueue;
 use std::future::Future;
 use std::io::ErrorKind;
-use std::io::{Seek, SeekFrom};
-use std::path::Path;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
 use std::task::Waker;
+use std::time::Duration;
 use tokio::fs::File;
-use tokio::io::{AsyncRead, AsyncSeek, AsyncSeekExt};
+use tokio::io::{AsyncBufRead, AsyncRead, AsyncSeek, AsyncSeekExt, ReadBuf};
+use tokio::sync::broadcast;
+use tokio::time::Sleep;
+
+//how much `AsyncBufRead` reads ahead.
+const BUFFER_SIZE: usize = 8 * 1024;
 
-pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
+#[macro_use]
+mod macros;
+mod builder;
+mod checkpoint;
+mod compression;
+mod error;
+mod event;
+mod file_set;
+mod history;
+mod hub;
+mod identity;
+mod lines;
+mod queue;
+mod records;
+mod source;
+#[cfg(feature = "testing")]
+pub mod testing;
+mod watched_dir;
+mod watched_glob;
+pub use builder::{Backend, DeletePolicy, StartPosition, TruncatePolicy, WatchedFileBuilder};
+pub use checkpoint::{Checkpoint, ParseCheckpointError};
+pub use error::Error;
+pub use event::FileEvent;
+pub use hub::WatchHub;
+pub use identity::{FileId, Fingerprint};
+pub use lines::{Line, Lines};
+pub use records::{Record, RecordBoundary, Records};
+pub use source::{EventCallback, WatchGuard, WatchSource};
+pub use watched_dir::{WatchedDir, WatchedDirBuilder};
+pub use watched_glob::WatchedGlob;
 
-#[derive(Clone, Copy)]
+/// What everything in this crate that can fail returns.
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Clone, Copy, Debug)]
 enum FileState {
     Modified,
     Deleted,
     WaitingEOF,
-    //on linux, a file that gets deleted won't actually be deleted until all inode's to it have been removed.
-    // NeedsReload,
 }
 
+type OpenFuture = Pin<Box<dyn Future<Output = tokio::io::Result<(File, Option<FileId>)>> + Send>>;
+
+//what we found out about the open file and our path after being woken up at EOF.
+struct Verification {
+    len: u64,
+    //the start of the file no longer matches the fingerprint, it was rewritten in place.
+    rewritten: bool,
+    //the file has no names left, it was deleted while we had it open.
+    unlinked: bool,
+    //what our path points at now, `None` if nothing or if the platform has no identities.
+    path_identity: Option<FileId>,
+}
+
+//the file is moved into the future and handed back once it's done.
+type VerifyFuture = Pin<Box<dyn Future<Output = (File, tokio::io::Result<Verification>)> + Send>>;
+
 enum FileOpenState {
-    Closed,
     Open,
     Seeking,
-    Opening(Pin<Box<dyn Future<Output = tokio::io::Result<File>> + Send>>),
+    Opening(OpenFuture),
+    Verifying(VerifyFuture),
+    //there is nothing at our path (yet), waiting for the watcher to see something get created.
+    Missing,
+}
+
+//check whether the file we have open (still positioned at `position`) is still the one at our path and whether it
+// got truncated or rewritten, leaving it positioned at `position` again.
+fn verify_file(
+    mut file: File,
+    path: PathBuf,
+    position: u64,
+    fingerprint: Option<Fingerprint>,
+) -> VerifyFuture {
+    Box::pin(async move {
+        let result = async {
+            //fstat the descriptor we have, so this works even if the path is gone.
+            let metadata = file.metadata().await?;
+            let rewritten = match fingerprint {
+                Some(fingerprint) if fingerprint.len > 0 => {
+                    let rewritten =
+                        Fingerprint::of(&mut file, fingerprint.len).await? != Some(fingerprint);
+                    file.seek(SeekFrom::Start(position)).await?;
+                    rewritten
+                }
+                _ => false,
+            };
+            let path_identity = match tokio::fs::metadata(&path).await {
+                Ok(metadata) => FileId::from_metadata(&metadata),
+                Err(e) if e.kind() == ErrorKind::NotFound => None,
+                Err(e) => return Err(e),
+            };
+            Ok(Verification {
+                len: metadata.len(),
+                rewritten,
+                unlinked: link_count(&metadata) == Some(0),
+                path_identity,
+            })
+        }
+        .await;
+        (file, result)
+    })
+}
+
+#[cfg(unix)]
+fn link_count(metadata: &std::fs::Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(metadata.nlink())
+}
+
+#[cfg(not(unix))]
+fn link_count(_metadata: &std::fs::Metadata) -> Option<u64> {
+    None
+}
+
+//what a watcher reports to.
+pub(crate) type Handler = Box<dyn FnMut(notify::Result<Event>) + Send>;
+
+//builds the platform's native watcher. `testing` hands in one that fails, to get at the fallbacks.
+pub(crate) type NativeWatcher = fn(Handler) -> notify::Result<Box<dyn Watcher + Send>>;
+
+pub(crate) fn recommended_watcher(handler: Handler) -> notify::Result<Box<dyn Watcher + Send>> {
+    Ok(Box::new(notify::recommended_watcher(handler)?))
+}
+
+//a watcher for `backend` that reports precise events to `handler`, still without anything to watch.
+pub(crate) fn new_watcher(
+    backend: Backend,
+    native: NativeWatcher,
+    mut handler: impl EventHandler,
+) -> Result<Box<dyn Watcher + Send>> {
+    let mut watcher = match backend {
+        Backend::Native => native(Box::new(move |res| handler.handle_event(res)))?,
+        Backend::Poll(poll_interval) => poll_watcher(handler, poll_interval)?,
+        Backend::Auto(poll_interval) => {
+            let handler = Arc::new(Mutex::new(handler));
+            match native(forward(&handler)) {
+                Ok(watcher) => watcher,
+                Err(e) if falls_back(&e) => {
+                    warn!(error = %e, "native watcher failed, polling instead");
+                    poll_watcher(forward(&handler), poll_interval)?
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    };
+    watcher.configure(Config::PreciseEvents(true))?;
+    Ok(watcher)
+}
+
+//a watcher for `backend` that's already watching `dir`. with `Backend::Auto` we also poll when
+// the native watcher starts fine but can't take `dir`, which is how the inotify watch limit shows.
+pub(crate) fn watch_dir(
+    backend: Backend,
+    native: NativeWatcher,
+    handler: impl EventHandler,
+    dir: &Path,
+    mode: RecursiveMode,
+) -> Result<Box<dyn Watcher + Send>> {
+    let Backend::Auto(poll_interval) = backend else {
+        let mut watcher = new_watcher(backend, native, handler)?;
+        watcher.watch(dir, mode)?;
+        return Ok(watcher);
+    };
+    let handler = Arc::new(Mutex::new(handler));
+    let native = native(forward(&handler)).and_then(|mut watcher| {
+        watcher.configure(Config::PreciseEvents(true))?;
+        watcher.watch(dir, mode)?;
+        Ok(watcher)
+    });
+    match native {
+        Ok(watcher) => Ok(watcher),
+        Err(e) if falls_back(&e) => {
+            warn!(error = %e, dir = %dir.display(), "native watcher failed, polling instead");
+            let mut watcher = new_watcher(
+                Backend::Poll(poll_interval),
+                recommended_watcher,
+                forward(&handler),
+            )?;
+            watcher.watch(dir, mode)?;
+            Ok(watcher)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn poll_watcher(
+    handler: impl EventHandler,
+    poll_interval: Duration,
+) -> notify::Result<Box<dyn Watcher + Send>> {
+    Ok(Box::new(PollWatcher::with_config(
+        handler,
+        PollWatcherConfig {
+            poll_interval,
+            compare_contents: false,
+        },
+    )?))
+}
+
+//lets a second watcher report to a handler we already gave away.
+fn forward<H: EventHandler>(handler: &Arc<Mutex<H>>) -> Handler {
+    let handler = handler.clone();
+    Box::new(move |res| handler.lock().unwrap().handle_event(res))
+}
+
+//whether polling could work where native notifications didn't. a path that isn't there won't
+// be any better.
+pub(crate) fn falls_back(e: &notify::Error) -> bool {
+    match &e.kind {
+        notify::ErrorKind::PathNotFound | notify::ErrorKind::WatchNotFound => false,
+        notify::ErrorKind::Io(e) => e.kind() != ErrorKind::NotFound,
+        _ => true,
+    }
+}
+
+//how many symlinks we follow before giving up on a loop, like the kernel's `ELOOP`.
+const MAX_SYMLINKS: usize = 40;
+
+//what `path` points to once its last component is no longer a symlink. the directories on the
+// way are left to `canonicalize`.
+async fn resolve_symlinks(path: &Path) -> PathBuf {
+    let mut path = path.to_path_buf();
+    for _ in 0..MAX_SYMLINKS {
+        match tokio::fs::read_link(&path).await {
+            //a relative target is relative to the directory the link lives in.
+            Ok(target) => path = path.parent().unwrap_or(Path::new("")).join(target),
+            //not a symlink, or nothing there yet.
+            Err(_) => break,
+        }
+    }
+    path
+}
+
+fn open_file(path: PathBuf) -> OpenFuture {
+    //we box::pin the future because tokio doesn't return a concrete type here
+    Box::pin(async move {
+        let file = File::open(path).await?;
+        let identity = FileId::from_metadata(&file.metadata().await?);
+        Ok((file, identity))
+    })
 }
 
 use std::sync::{Arc, Mutex};
 struct SharedState {
-    //todo: probably want to make this into some kind of queue instead of just the last seen event.
-    state: FileState,
+    //what the watcher saw since the reader last looked.
+    events: EventQueue,
+    //the watcher broke, handed to the reader on its next poll.
+    error: Option<notify::Error>,
     waker: Option<Waker>,
 }
 
@@ -41,10 +281,20 @@ struct WakerWrapper {
 }
 
 impl WakerWrapper {
-    fn wake(&mut self, state: FileState) -> bool {
+    fn push(&mut self, event: PathEvent) -> bool {
+        let mut shared_state = self.shared_state.lock().unwrap();
+        shared_state.events.push(event);
+        if let Some(waker) = shared_state.waker.take() {
+            waker.wake();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn fail(&mut self, error: notify::Error) -> bool {
         let mut shared_state = self.shared_state.lock().unwrap();
-        shared_state.state = state;
-        // shared_state.paused = false;
+        shared_state.error = Some(error);
         if let Some(waker) = shared_state.waker.take() {
             waker.wake();
             true
@@ -54,6 +304,34 @@ impl WakerWrapper {
     }
 }
 
+//what a notify event means for the file living at `path`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PathEvent {
+    Modified,
+    Deleted,
+    //the path was renamed away from us or had a new file put in its place.
+    Replaced,
+}
+
+impl PathEvent {
+    //we watch the parent directory, so most events we get are about some other file.
+    fn classify(event: &Event, path: &Path) -> Option<Self> {
+        let is_ours = |p: &PathBuf| p == path;
+        match event.kind {
+            //whether we got renamed away or something got renamed onto us, the path no longer points at our file.
+            EventKind::Modify(ModifyKind::Name(_)) | EventKind::Create(_)
+                if event.paths.iter().any(is_ours) =>
+            {
+                Some(PathEvent::Replaced)
+            }
+            EventKind::Remove(_) if event.paths.iter().any(is_ours) => Some(PathEvent::Deleted),
+            //this includes attribute changes (IN_ATTRIB), like the link count dropping, which gets checked at the next EOF.
+            EventKind::Modify(_) if event.paths.iter().any(is_ours) => Some(PathEvent::Modified),
+            _ => None,
+        }
+    }
+}
+
 /*
 File watching
 
@@ -61,233 +339,787 @@ initial state: read file as normal. Track total bytes read and last update seen.
 on eof:
     - check a 'last update seen' flag vs a 'last update submitted' flag to see if file might have changed by checking neq. if so, update last seen and retry
     - once eof _and_ flags are equal, set waker and return pending.
-    - when woken up check append vs. recreate
+    - when woken up, fstat the descriptor and stat the path before reading on. that tells appends, truncation (length),
+      deletion (link count) and replacement (identity) apart without closing the descriptor.
+on delete:
+    - the parent directory watch sees our name go away even though we keep the file open, the link count catches it if the watcher didn't.
+    - the path is only opened again once something new lives there.
+on rotation (tail -F):
+    - the watcher sees our path renamed away or recreated and marks the shared state as replaced.
+    - at EOF we try to open whatever lives at the path now, if nothing does yet we wait for the watcher to see it get created.
+    - the old descriptor stays open until then and gets drained one last time before we switch over, the writer might still be appending to it.
+    - the new file is read from the start.
 */
+/// A file that reads like a [`File`], except that at EOF it waits for more to be written instead
+/// of ending, following the path through rotations and truncations like `tail -F`.
 pub struct WatchedFile {
     file: Option<File>,
+    //(device, inode) on platforms that have them, used to tell if the path still points at the file we have open.
+    file_identity: Option<FileId>,
+    //of the bytes we've read so far, for checkpoints.
+    fingerprint: Fingerprint,
+    //the file that took over our path after a rotation, waiting for `file` to be drained.
+    next_file: Option<(File, Option<FileId>)>,
     file_state: FileOpenState,
+    //what the events picked up from the watcher so far add up to.
+    state: FileState,
+    //set when the path stopped pointing at the file we have open (renamed away or something new was put in its place).
+    // kept apart from `state` so a later modify event can't hide a rotation.
+    replaced: bool,
     last_seek_location: u64,
+    //goes up every time we start over in a new or truncated file, so offsets can be told apart.
+    generation: u64,
+    //rotated copies we read through before getting to `file`.
+    history: Option<History>,
+    //we went to sleep at EOF, so whatever woke us has to be checked before reading on.
     at_eof: bool,
+    //also compare the start of the file against `fingerprint` when checking.
+    compare_contents: bool,
     shared_state: Arc<Mutex<SharedState>>,
-    //on non-windows OS we need to close and reopen the file occasionaly to detect deletes.
+    //when the file gets rotated we have to open whatever replaced it
     // so we remember the PathBuf to pass to tokio::file::Open
-    // #[cfg(not(target_os = "windows"))]
     path: std::path::PathBuf,
-    //only here to tie the lifetimes together
-    _watcher: RecommendedWatcher,
+    on_delete: DeletePolicy,
+    on_truncate: TruncatePolicy,
+    debounce: Option<Duration>,
+    debounce_sleep: Option<Pin<Box<Sleep>>>,
+    //set when we went to sleep at EOF, so the first poll after being woken up waits out the debounce.
+    debouncing: bool,
+    events: broadcast::Sender<FileEvent>,
+    //what to tell `events` once we switch over to the file that's being opened.
+    replacement_event: FileEvent,
+    //whether we already sent `FileEvent::Deleted` for the current file.
+    reported_deleted: bool,
+    //with `TruncatePolicy::Error`, the truncation every read from then on fails with.
+    truncated: Option<(u64, u64)>,
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+    //stop at the first EOF instead of waiting for more.
+    follow: bool,
+    //switch over to the file that replaced ours, instead of ending once ours is drained.
+    follow_rotation: bool,
+    //a seek asked for through `AsyncSeek`.
+    seek: Option<UserSeek>,
+    //for `AsyncBufRead`, allocated on first use. `buffer_pos..buffer_filled` is still to be handed out.
+    buffer: Vec<u8>,
+    buffer_pos: usize,
+    buffer_filled: usize,
+    //only here to tie the lifetimes together. not following a file we already have open needs no watcher.
+    _watcher: Option<WatchGuard>,
 }
 
 impl AsyncRead for WatchedFile {
-    //todo: cleanup pins and extend guarantess
     fn poll_read(
         self: Pin<&mut Self>,
         cx: &mut Context<'_>,
-        buf: &mut tokio::io::ReadBuf<'_>,
+        buf: &mut ReadBuf<'_>,
     ) -> Poll<std::result::Result<(), std::io::Error>> {
-        let this = unsafe { self.get_unchecked_mut() };
+        //nothing in here needs structural pinning, so `WatchedFile` is `Unpin`.
+        let this = self.get_mut();
+        if this.buffer_pos == this.buffer_filled && buf.remaining() >= BUFFER_SIZE {
+            //nothing buffered and the caller has room for more than we'd buffer, skip the copy.
+            return this.poll_read_file(cx, buf);
+        }
+        let available = std::task::ready!(this.poll_fill(cx))?;
+        let read = available.len().min(buf.remaining());
+        buf.put_slice(&available[..read]);
+        this.buffer_pos += read;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncBufRead for WatchedFile {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<&[u8]>> {
+        self.get_mut().poll_fill(cx)
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        let this = self.get_mut();
+        this.buffer_pos = (this.buffer_pos + amt).min(this.buffer_filled);
+    }
+}
+
+impl WatchedFile {
+    //only refilled once it's empty, and a single read never crosses into the next generation, so
+    // the buffer never mixes bytes from before and after a rotation or truncation.
+    fn poll_fill(&mut self, cx: &mut Context<'_>) -> Poll<std::io::Result<&[u8]>> {
+        if self.buffer_pos == self.buffer_filled {
+            let mut buffer = std::mem::take(&mut self.buffer);
+            buffer.resize(BUFFER_SIZE, 0);
+            let mut read_buf = ReadBuf::new(&mut buffer);
+            let result = self.poll_read_file(cx, &mut read_buf);
+            let filled = read_buf.filled().len();
+            self.buffer = buffer;
+            std::task::ready!(result)?;
+            self.buffer_pos = 0;
+            self.buffer_filled = filled;
+        }
+        Poll::Ready(Ok(&self.buffer[self.buffer_pos..self.buffer_filled]))
+    }
+
+    //what we read but didn't hand out yet.
+    fn buffered(&self) -> u64 {
+        (self.buffer_filled - self.buffer_pos) as u64
+    }
+
+    fn discard_buffer(&mut self) {
+        self.buffer_pos = 0;
+        self.buffer_filled = 0;
+    }
+
+    fn poll_read_file(
+        &mut self,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = self;
+        #[cfg(feature = "tracing")]
+        let span = this.span.clone();
+        #[cfg(feature = "tracing")]
+        let _enter = span.enter();
         let size_before_poll = buf.filled().len();
 
-        //if the file was close last poll, start a future to open it.
-        if let FileOpenState::Closed = &mut this.file_state {
-            //we box::pin the future because tokio doesn't return a concrete type here
-            this.at_eof = false;
-            this.file_state = FileOpenState::Opening(Box::pin(File::open(this.path.clone())));
+        if this.seek.is_some() {
+            return Poll::Ready(Err(std::io::Error::other(
+                "seek is pending, call poll_complete before reading",
+            )));
+        }
+        if let Some((old_len, new_len)) = this.truncated {
+            //whatever the file holds now isn't a continuation of what we read.
+            return Poll::Ready(Err(Error::Truncated { old_len, new_len }.into()));
+        }
+        if let Some(error) = this.shared_state.lock().unwrap().error.take() {
+            return Poll::Ready(Err(Error::Watch(error).into()));
+        }
+        if let Some(history) = &mut this.history {
+            match history.poll_read(cx, buf)? {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Progress::Read { next_segment }) => {
+                    if next_segment {
+                        this.generation += 1;
+                    }
+                    return Poll::Ready(Ok(()));
+                }
+                Poll::Ready(Progress::Done { started }) => {
+                    debug!("read all rotated copies, carrying on with the file");
+                    if started {
+                        this.generation += 1;
+                    }
+                    this.history = None;
+                }
+            }
+        }
+        if this.debouncing {
+            let debounce = this.debounce.unwrap_or_default();
+            let sleep = this
+                .debounce_sleep
+                .get_or_insert_with(|| Box::pin(tokio::time::sleep(debounce)));
+            if sleep.as_mut().poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+            this.debounce_sleep = None;
+            this.debouncing = false;
+        }
+        if this.at_eof {
+            if let FileOpenState::Open = this.file_state {
+                this.start_verify();
+            }
+        }
+        if let FileOpenState::Missing = &mut this.file_state {
+            this.take_events(cx.waker());
+            if this.replaced {
+                this.replaced = false;
+                this.file_state = FileOpenState::Opening(open_file(this.path.clone()));
+            } else {
+                return Poll::Pending;
+            }
         }
         if let FileOpenState::Opening(fut) = &mut this.file_state {
-            //if the file was currently being opened, drive that future
+            //if the file at our path is currently being opened, drive that future
             match Pin::new(fut).poll(cx) {
                 Poll::Pending => return Poll::Pending,
                 Poll::Ready(Err(e)) => {
-                    //todo: handle file not found => EOF
+                    this.file_state = if this.file.is_some() {
+                        FileOpenState::Open
+                    } else {
+                        FileOpenState::Missing
+                    };
                     return match e.kind() {
-                        //we include permission denied here because the file _previously_ existed with this name and the right permissions
-                        //so if the file gets deleted/recreated with new permissions it would trigger that error which we consider "not the same file"
-                        ErrorKind::NotFound | ErrorKind::PermissionDenied => Poll::Ready(Ok(())),
+                        ErrorKind::NotFound => {
+                            this.take_events(cx.waker());
+                            if let (FileState::Deleted, Some(result)) =
+                                (this.state, this.on_delete.finished())
+                            {
+                                //the path was removed rather than rotated, nothing left to follow.
+                                if !this.reported_deleted {
+                                    this.reported_deleted = true;
+                                    let _ = this.events.send(FileEvent::Deleted);
+                                }
+                                Poll::Ready(result)
+                            } else if this.replaced {
+                                //something showed up at the path while we were trying to open it, go again.
+                                cx.waker().wake_by_ref();
+                                Poll::Pending
+                            } else {
+                                //rotated away (or never there) and nothing new there yet, wait for the watcher to see it get created.
+                                Poll::Pending
+                            }
+                        }
+                        //the file _previously_ existed with this name and the right permissions
+                        //so if the file gets deleted/recreated with new permissions we end up here, which we consider "not the same file"
+                        ErrorKind::PermissionDenied => {
+                            Poll::Ready(Err(Error::PermissionChanged.into()))
+                        }
                         _ => Poll::Ready(Err(e)),
                     };
                 }
-                //if the file is opened succesfully, set this.file and continue as normal.
-                Poll::Ready(Ok(mut file)) => {
-                    //on "not windows" we close the file at every EOF so try and seek ahead
-                    #[cfg(not(target_os = "windows"))]
-                    {
-                        Pin::new(&mut file).start_seek(SeekFrom::End(0))?;
-                        this.file_state = FileOpenState::Seeking;
-                        this.file = Some(file);
-                    }
-                    //on windows this only occurs if the file was truncated so we don't have to do the seek dance and just start from zero.
-                    #[cfg(target_os = "windows")]
-                    {
+                Poll::Ready(Ok((file, identity))) => {
+                    this.file_state = FileOpenState::Open;
+                    if this.file.is_none() {
+                        //the first file to show up at our path, read it from the start.
                         this.file = Some(file);
+                        this.file_identity = identity;
+                        this.fingerprint = Fingerprint::default();
+                        this.last_seek_location = 0;
+                        debug!(offset = 0, "opened file");
+                    } else if identity.is_some() && identity == this.file_identity {
+                        //the event that sent us here was late, the path still points at the file we're reading.
+                    } else {
+                        //if the file is opened succesfully, hold on to it until we're done with the old one.
+                        this.next_file = Some((file, identity));
+                        debug!("opened the file that replaced ours");
                     }
-                    eprintln!("\t reopend file {:?}!", this.path);
                 }
             }
         }
-        {
+        if let FileOpenState::Verifying(fut) = &mut this.file_state {
+            let (file, result) = match Pin::new(fut).poll(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(done) => done,
+            };
+            this.file = Some(file);
+            this.file_state = FileOpenState::Open;
+            let verification = result?;
+            if verification.unlinked && !matches!(this.state, FileState::Deleted) {
+                //the watcher missed the delete, the link count doesn't lie.
+                debug!("file has no links left");
+                this.state = FileState::Deleted;
+            }
+            if let (Some(current), Some(ours)) = (verification.path_identity, this.file_identity) {
+                if current != ours {
+                    //the watcher missed our path getting replaced, treat it like it didn't.
+                    debug!("path points at a different file than the one we have open");
+                    this.replaced = true;
+                }
+            }
+            if verification.len < this.last_seek_location || verification.rewritten {
+                let old_len = this.last_seek_location;
+                let new_len = verification.len;
+                debug!(old_len, new_len, verification.rewritten, "file truncated");
+                let _ = this.events.send(FileEvent::Truncated { old_len, new_len });
+                if let TruncatePolicy::Error = this.on_truncate {
+                    this.truncated = Some((old_len, new_len));
+                    return Poll::Ready(Err(Error::Truncated { old_len, new_len }.into()));
+                }
+                //start over from the beginning, the seek completes on the next poll.
+                this.last_seek_location = 0;
+                this.generation += 1;
+                this.fingerprint = Fingerprint::default();
+                let file = Pin::new(this.file.as_mut().unwrap());
+                file.start_seek(SeekFrom::Start(0))?;
+                this.file_state = FileOpenState::Seeking;
+                if let TruncatePolicy::Report = this.on_truncate {
+                    return Poll::Ready(Err(Error::Truncated { old_len, new_len }.into()));
+                }
+            }
+        }
+        if let FileOpenState::Seeking = this.file_state {
             let file = Pin::new(this.file.as_mut().unwrap());
-            let seek_to = if let FileOpenState::Seeking = &mut this.file_state {
-                // let file = unsafe { core::pin::Pin::new_unchecked() };
-                match file.poll_complete(cx)? {
-                    Poll::Pending => {
-                        return Poll::Pending;
+            match file.poll_complete(cx)? {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(_) => this.file_state = FileOpenState::Open,
+            }
+        }
+        let file = Pin::new(this.file.as_mut().unwrap());
+        //try and read from the file into the buffer
+        match file.poll_read(cx, buf) {
+            Poll::Pending => {
+                //return pending as-is
+                Poll::Pending
+            }
+            //return errors as-is
+            Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+            Poll::Ready(Ok(())) => {
+                let bytes_read = buf.filled().len() - size_before_poll;
+                if this.fingerprint.len == this.last_seek_location {
+                    this.fingerprint.update(&buf.filled()[size_before_poll..]);
+                }
+                this.last_seek_location += bytes_read as u64;
+                if bytes_read != 0 {
+                    //as long as the file has not reached EOF we return the results as normal
+                    this.at_eof = false;
+                    return Poll::Ready(Ok(()));
+                }
+                if let Some((next_file, identity)) = this.next_file.take() {
+                    if !this.follow_rotation {
+                        debug!(
+                            offset = this.last_seek_location,
+                            "finished rotated file, not following its replacement"
+                        );
+                        return Poll::Ready(Ok(()));
                     }
-                    Poll::Ready(size) => {
-                        if size < this.last_seek_location {
-                            eprintln!("file truncated");
-                            this.last_seek_location = 0;
-                            // file.start_seek(SeekFrom::Start(0))?;
-                            // cx.waker().wake_by_ref();
-                            // return Poll::Pending;
-                            Some(0)
-                        } else if size > this.last_seek_location {
-                            //we overshot, end is now past where we left off
-                            // file.start_seek(SeekFrom::Start(this.last_seek_location))?;
-                            // cx.waker().wake_by_ref();
-                            // return Poll::Pending;
-                            Some(this.last_seek_location)
+                    //the rotated file is fully drained, carry on with the one that replaced it from the start.
+                    debug!(
+                        offset = this.last_seek_location,
+                        "finished rotated file, switching to its replacement"
+                    );
+                    this.file = Some(next_file);
+                    this.file_identity = identity;
+                    this.fingerprint = Fingerprint::default();
+                    this.last_seek_location = 0;
+                    this.generation += 1;
+                    this.at_eof = false;
+                    this.reported_deleted = false;
+                    let _ = this.events.send(this.replacement_event);
+                    this.state = FileState::Modified;
+                    cx.waker().wake_by_ref();
+                    return Poll::Pending;
+                }
+                if !this.follow {
+                    //read through once, like a plain file.
+                    trace!(
+                        offset = this.last_seek_location,
+                        "reached the end, not following"
+                    );
+                    return Poll::Ready(Ok(()));
+                }
+                this.take_events(cx.waker());
+                if this.replaced {
+                    //our path points somewhere else now, go and open whatever lives there.
+                    this.replaced = false;
+                    this.replacement_event = match this.state {
+                        FileState::Deleted => FileEvent::Recreated,
+                        _ => FileEvent::Rotated,
+                    };
+                    this.file_state = FileOpenState::Opening(open_file(this.path.clone()));
+                    cx.waker().wake_by_ref();
+                    return Poll::Pending;
+                }
+                match this.state {
+                    FileState::Deleted => {
+                        //we hit EOF on our open file descriptor and the OS has reported that the file has been deleted sometime between us opening and hitting EOF
+                        // so this is truely EOF.
+                        if !this.reported_deleted {
+                            this.reported_deleted = true;
+                            let _ = this.events.send(FileEvent::Deleted);
+                        }
+                        if let Some(result) = this.on_delete.finished() {
+                            return Poll::Ready(result);
+                        }
+                        //unless we were asked to wait for it to come back.
+                        this.state = FileState::WaitingEOF;
+                        this.replacement_event = FileEvent::Recreated;
+                        this.file_state = FileOpenState::Opening(open_file(this.path.clone()));
+                        cx.waker().wake_by_ref();
+                        Poll::Pending
+                    }
+                    _ => {
+                        //we've hit EOF but the file hasn't been deleted yet, `take_events` already told the watcher how to wake us.
+                        //if the watcher saw a change since we last went to sleep, we might have missed its wake up.
+                        let changed = matches!(this.state, FileState::Modified);
+                        //and set the current state to 'waiting for new events.
+                        this.state = FileState::WaitingEOF;
+                        //whatever wakes us up, check the file before reading on: it might have been truncated or replaced
+                        // without us seeing anything new to read.
+                        if changed {
+                            this.start_verify();
+                            cx.waker().wake_by_ref();
                         } else {
-                            // this.file_state = FileOpenState::Open;
-                            None
+                            this.at_eof = true;
+                            this.debouncing = this.debounce.is_some();
                         }
+                        Poll::Pending
                     }
                 }
-            } else {
-                None
+            }
+        }
+    }
+}
+
+//a seek only starts once whatever the state machine was doing with the file is finished.
+enum UserSeek {
+    Requested(SeekFrom),
+    Started(SeekFuture),
+}
+
+//like `VerifyFuture`, the file is handed back once the seek is done.
+type SeekFuture = Pin<Box<dyn Future<Output = (File, std::io::Result<u64>)> + Send>>;
+
+//seek `file` to `position`, as long as that's within the file. further on we'd take the file
+// for truncated once we get there.
+fn seek_file(mut file: File, position: SeekFrom) -> SeekFuture {
+    Box::pin(async move {
+        let result = async {
+            let len = file.metadata().await?.len();
+            let target = match position {
+                SeekFrom::Start(n) => Some(n),
+                SeekFrom::End(n) => len.checked_add_signed(n),
+                SeekFrom::Current(n) => file.stream_position().await?.checked_add_signed(n),
             };
-            if let Some(seek_to) = seek_to {
-                let file = Pin::new(this.file.as_mut().unwrap());
-                file.start_seek(SeekFrom::Start(seek_to))?;
-                cx.waker().wake_by_ref();
-                return Poll::Pending;
-            } else {
-                this.file_state = FileOpenState::Open;
+            match target {
+                Some(target) if target <= len => file.seek(SeekFrom::Start(target)).await,
+                _ => Err(std::io::Error::new(
+                    ErrorKind::InvalidInput,
+                    "seek to a position outside of the file",
+                )),
             }
         }
-        let file = Pin::new(this.file.as_mut().unwrap());
-        //take the file out of the pin
-        return if let FileOpenState::Open = &mut this.file_state {
-            //and pin it in place here.
-            // this is safe because we have a reference to member of a pinned struct
-            // let file = unsafe { core::pin::Pin::new_unchecked(file) };
-            //try and read from the file into the buffer
-            match file.poll_read(cx, buf) {
-                Poll::Pending => {
-                    //return pending as-is
-                    Poll::Pending
-                }
-                //return errors as-is
-                Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
-                Poll::Ready(Ok(())) => {
-                    let bytes_read = buf.filled().len() - size_before_poll;
-                    this.last_seek_location += bytes_read as u64;
-                    if bytes_read != 0 {
-                        //as long as the file has not reached EOF we return the results as normal
-                        this.at_eof = false;
-                        Poll::Ready(Ok(()))
-                    } else {
-                        let mut lock = this.shared_state.lock().unwrap();
-                        let shared_state = lock.borrow_mut();
-                        match shared_state.state {
-                            FileState::Deleted => {
-                                //we hit EOF on our open file descriptor and the OS has reported that the file has been deleted sometime between us opening and hitting EOF
-                                // so this is truely EOF.
-                                Poll::Ready(Ok(()))
-                            }
-                            _ => {
-                                //we've hit EOF but the file hasn't been deleted yet
-                                //so we tell the file watched how to wake us
-                                shared_state.waker = Some(cx.waker().clone());
-                                //and set the current state to 'waiting for new events.
-                                shared_state.state = FileState::WaitingEOF;
-                                //if we hit EOF twice in a row, that means the file was most likely truncated.
-                                // reseek to zero.
-                                if this.at_eof {
-                                    //...
-                                    // either reseek to zero (maybe reopen file?)
-                                    // or return an error?
-                                    eprintln!("File truncated");
-                                    this.file = None;
-                                    this.file_state = FileOpenState::Closed;
-                                    eprintln!("\t CLOSED file");
-                                    cx.waker().wake_by_ref();
-                                    Poll::Pending
-                                } else {
-                                    //mark that last poll we were at EOF.
-                                    this.at_eof = true;
-                                    //on Linux (and possibly other non-windows OS) we have to close our file to trigger a delete event from being generated.
-                                    #[cfg(not(target_os = "windows"))]
-                                    {
-                                        this.file = None;
-                                        this.file_state = FileOpenState::Closed;
-                                        eprintln!("\t CLOSED file {:?} at {}!", this.path, this.last_seek_location);
-                                    }
-                                    Poll::Pending
-                                }
-                            }
+        .await;
+        (file, result)
+    })
+}
+
+/// Seeking moves around in the file at [`path`](WatchedFile::path), like on a [`File`]. Rotated
+/// copies that are still to be read from [`read_rotated`](WatchedFileBuilder::read_rotated) are
+/// skipped.
+///
+/// Seeking before the start or past the end fails with [`ErrorKind::InvalidInput`] and leaves
+/// the position where it was.
+impl AsyncSeek for WatchedFile {
+    fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> std::io::Result<()> {
+        let this = self.get_mut();
+        if this.seek.is_some() {
+            return Err(std::io::Error::other(
+                "other file operation is pending, call poll_complete before start_seek",
+            ));
+        }
+        let position = match position {
+            //the caller is where we handed out up to, not where we read up to.
+            SeekFrom::Current(n) if this.history.is_none() => {
+                SeekFrom::Current(n - this.buffered() as i64)
+            }
+            position => position,
+        };
+        this.seek = Some(UserSeek::Requested(position));
+        Ok(())
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<u64>> {
+        let this = self.get_mut();
+        let Some(seek) = &this.seek else {
+            return Poll::Ready(Ok(this.position()));
+        };
+        if let UserSeek::Requested(position) = *seek {
+            //get the file back from whatever we were doing with it.
+            match &mut this.file_state {
+                FileOpenState::Verifying(fut) => {
+                    let (file, result) = std::task::ready!(Pin::new(fut).poll(cx));
+                    this.file = Some(file);
+                    this.file_state = FileOpenState::Open;
+                    //we're going somewhere else anyway, only hold on to what we can't find out again.
+                    if let Ok(verification) = result {
+                        if verification.unlinked {
+                            this.state = FileState::Deleted;
+                        }
+                        if verification.path_identity.is_some()
+                            && verification.path_identity != this.file_identity
+                        {
+                            this.replaced = true;
                         }
                     }
                 }
+                FileOpenState::Seeking => {
+                    let file = Pin::new(this.file.as_mut().unwrap());
+                    if let Err(e) = std::task::ready!(file.poll_complete(cx)) {
+                        this.seek = None;
+                        return Poll::Ready(Err(e));
+                    }
+                    this.file_state = FileOpenState::Open;
+                }
+                _ => {}
             }
-        } else {
-            unreachable!("we already exhausted all other options")
+            let Some(file) = this.file.take() else {
+                this.seek = None;
+                return Poll::Ready(Err(std::io::Error::new(
+                    ErrorKind::NotFound,
+                    "the file doesn't exist yet",
+                )));
+            };
+            this.seek = Some(UserSeek::Started(seek_file(file, position)));
+        }
+        let Some(UserSeek::Started(fut)) = &mut this.seek else {
+            unreachable!("the seek was started above");
         };
+        let (file, result) = std::task::ready!(fut.as_mut().poll(cx));
+        this.file = Some(file);
+        this.seek = None;
+        //a seek that failed didn't move the file, so what we buffered is still next.
+        let position = result?;
+        this.discard_buffer();
+        debug!(offset = position, "seeked");
+        if let Some(history) = this.history.take() {
+            if history.started() {
+                this.generation += 1;
+            }
+        }
+        this.last_seek_location = position;
+        //whatever we slept on at the old position doesn't matter any more.
+        this.at_eof = false;
+        Poll::Ready(Ok(position))
+    }
+}
+
+impl WatchedFile {
+    //pick up what the watcher saw since we last looked, in order, and have it wake us up for whatever comes next.
+    fn take_events(&mut self, waker: &Waker) {
+        let mut shared_state = self.shared_state.lock().unwrap();
+        for event in shared_state.events.drain() {
+            trace!(?event, state = ?self.state, "picked up event");
+            match event {
+                //writes to a deleted file don't bring it back.
+                PathEvent::Modified if matches!(self.state, FileState::Deleted) => {}
+                PathEvent::Modified => self.state = FileState::Modified,
+                PathEvent::Deleted => self.state = FileState::Deleted,
+                PathEvent::Replaced => self.replaced = true,
+            }
+        }
+        shared_state.waker = Some(waker.clone());
+    }
+    //move the open file into a `Verifying` state, we can't read from it until that's done.
+    fn start_verify(&mut self) {
+        self.at_eof = false;
+        let file = self.file.take().unwrap();
+        let fingerprint = self.compare_contents.then_some(self.fingerprint);
+        trace!(offset = self.last_seek_location, "checking file after EOF");
+        self.file_state = FileOpenState::Verifying(verify_file(
+            file,
+            self.path.clone(),
+            self.last_seek_location,
+            fingerprint,
+        ));
     }
 }
 
+impl DeletePolicy {
+    //what poll_read returns once a deleted file has been drained, `None` if we should keep going.
+    fn finished(self) -> Option<std::io::Result<()>> {
+        match self {
+            DeletePolicy::End => Some(Ok(())),
+            DeletePolicy::WaitForRecreation => None,
+            DeletePolicy::Error => Some(Err(Error::FileVanished.into())),
+        }
+    }
+}
+
+//how many events a slow `events()` receiver can fall behind before it starts missing them.
+const EVENT_CAPACITY: usize = 64;
+
 impl WatchedFile {
+    /// Subscribe to rotations, truncations and deletions of the file.
+    ///
+    /// Only events that happen after subscribing are received, a receiver that falls more than 64
+    /// events behind gets [`RecvError::Lagged`](broadcast::error::RecvError::Lagged).
+    pub fn events(&self) -> broadcast::Receiver<FileEvent> {
+        self.events.subscribe()
+    }
+    /// Where we are in which file, to resume from after a restart.
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            offset: self.position(),
+            file_id: self.file_identity,
+            fingerprint: self.fingerprint,
+        }
+    }
+    /// Where reading carries on in the file at [`path`](Self::path). Rotated copies read first
+    /// with [`read_rotated`](WatchedFileBuilder::read_rotated) don't count.
+    pub fn position(&self) -> u64 {
+        match &self.history {
+            Some(_) => self.last_seek_location,
+            None => self.last_seek_location - self.buffered(),
+        }
+    }
+    /// Goes up every time the file is rotated, recreated or truncated, like
+    /// [`Line::generation`]. What [`fill_buf`](tokio::io::AsyncBufReadExt::fill_buf) returns always
+    /// belongs to the current one.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+    /// The path we're watching, whatever file it points to right now.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    /// The identity of the file we're reading, on platforms that have one. It changes when the
+    /// path gets rotated or recreated and we switch over to the new file.
+    pub fn file_id(&self) -> Option<FileId> {
+        self.file_identity
+    }
+    //where the next byte read comes from, in the rotated copy we're reading or in the file.
+    pub(crate) fn offset(&self) -> u64 {
+        match &self.history {
+            Some(history) => history.offset() - self.buffered(),
+            None => self.last_seek_location - self.buffered(),
+        }
+    }
+    //the rotated copy we're reading, or the file once we're past them.
+    pub(crate) fn current_path(&self) -> &Path {
+        self.history
+            .as_ref()
+            .and_then(History::path)
+            .unwrap_or(&self.path)
+    }
+    /// The file as a stream of lines, each with where it came from.
+    pub fn lines(self) -> Lines {
+        Lines::new(self)
+    }
+    /// Options for watching `path`, for anything [`new`](Self::new) and [`tail`](Self::tail)
+    /// don't cover.
+    pub fn builder(path: impl AsRef<Path>) -> WatchedFileBuilder {
+        WatchedFileBuilder::new(path)
+    }
+    /// Only what gets written to `path` from now on, like `tail -F -n 0`.
     pub async fn tail(path: impl AsRef<Path>) -> Result<Self> {
-        let mut this = Self::new(path).await?;
-        this.last_seek_location =  this.file.as_mut().unwrap().seek(SeekFrom::End(0)).await?;
-        Ok(this)
+        Self::builder(path).start(StartPosition::End).build().await
     }
+    /// Everything in `path` from the start, and whatever gets written to it after that.
     pub async fn new(path: impl AsRef<Path>) -> Result<Self> {
+        Self::builder(path).build().await
+    }
+    /// Like [`new`](Self::new), but if nothing exists at `path` yet we wait for it to be created
+    /// instead of failing. The file is read from the start once it shows up.
+    pub async fn wait_for(path: impl AsRef<Path>) -> Result<Self> {
+        Self::builder(path).wait_for_creation(true).build().await
+    }
+    //`file` is the already opened file together with the offset it was seeked to and the fingerprint of everything before that.
+    // a `compressed` file is read decompressed before we get to that offset, after any rotated copies.
+    pub(crate) async fn watch(
+        options: WatchedFileBuilder,
+        file: Option<(File, u64, Fingerprint)>,
+        compressed: bool,
+    ) -> Result<Self> {
+        let path = options.path;
         let shared_state = Arc::new(Mutex::new(SharedState {
-            state: FileState::Modified,
+            events: EventQueue::new(),
+            error: None,
             waker: None,
         }));
-        let file = File::open(path.as_ref()).await?;
+        //we watch the parent directory instead of the file itself, that way we still see the path once the file behind it
+        // gets renamed (log rotation) and a new one gets created in its place.
+        // a symlink doesn't change when its target is written to, so that's the one we watch for.
+        let target = resolve_symlinks(&path).await;
+        let file_name = target.file_name().ok_or_else(|| {
+            std::io::Error::new(ErrorKind::InvalidInput, "path does not point to a file")
+        })?;
+        let parent = match target.parent() {
+            Some(parent) if parent != Path::new("") => parent,
+            _ => Path::new("."),
+        };
+        //notify reports paths relative to what we asked it to watch, canonicalize so we can compare against them.
+        let parent = tokio::fs::canonicalize(parent).await?;
+        let watched_path = parent.join(file_name);
         let mut waker = WakerWrapper {
             shared_state: shared_state.clone(),
         };
-        let mut watcher = notify::recommended_watcher(move |res| {
-            eprintln!("\t got result: {:?}\n", res);
-            match res {
-                Ok(Event {
-                    kind: EventKind::Modify(ModifyKind::Name(_)),
-                    ..
-                })
-                | Ok(Event {
-                    kind: EventKind::Remove(_),
-                    ..
-                }) => {
-                    // println!("file was removed");
-                    waker.wake(FileState::Deleted);
-                    return;
+        #[cfg(feature = "tracing")]
+        let span = tracing::debug_span!("watched_file", path = %path.display());
+        #[cfg(feature = "tracing")]
+        let watcher_span = span.clone();
+        let handler = move |res: notify::Result<Event>| match res {
+            Ok(event) => match PathEvent::classify(&event, &watched_path) {
+                Some(PathEvent::Replaced) => {
+                    debug!(parent: &watcher_span, ?event, "path replaced");
+                    waker.push(PathEvent::Replaced);
+                }
+                Some(PathEvent::Deleted) => {
+                    debug!(parent: &watcher_span, ?event, "file deleted");
+                    waker.push(PathEvent::Deleted);
                 }
-                Ok(Event {
-                    kind: EventKind::Modify(_),
-                    ..
-                }) => {
-                    // println!("file was modified");
-                    waker.wake(FileState::Modified);
+                Some(PathEvent::Modified) => {
+                    trace!(parent: &watcher_span, ?event, "file modified");
+                    waker.push(PathEvent::Modified);
                 }
-                Err(e) => println!("watch error: {:?}", e),
-                _ => { /*println!("dont know this event");*/ }
+                None => { /*not about our file*/ }
+            },
+            Err(e) => {
+                warn!(parent: &watcher_span, error = %e, "watcher failed");
+                waker.fail(e);
             }
-        })?;
-        watcher.configure(Config::PreciseEvents(true))?;
-        watcher.watch(path.as_ref(), RecursiveMode::NonRecursive)?;
+        };
+        let watcher = match &options.source {
+            _ if !options.follow && file.is_some() => None,
+            Some(source) => Some(source.watch(&parent, Box::new(handler))?),
+            None => Some(WatchGuard::new(watch_dir(
+                options.backend,
+                options.native,
+                handler,
+                &parent,
+                RecursiveMode::NonRecursive,
+            )?)),
+        };
 
+        let mut rotated = if options.read_rotated {
+            history::find_rotated(&path).await?
+        } else {
+            Default::default()
+        };
+        if compressed {
+            rotated.push_back(path.clone());
+        }
+        let history = (options.read_rotated || compressed).then(|| History::new(rotated));
+
+        //if we weren't handed a file, we only try opening it now that the watcher is running.
+        // that way we can't miss it getting created in between.
+        let (file, file_identity, file_state, last_seek_location, fingerprint) = match file {
+            Some((file, offset, fingerprint)) => {
+                let identity = FileId::from_metadata(&file.metadata().await?);
+                (
+                    Some(file),
+                    identity,
+                    FileOpenState::Open,
+                    offset,
+                    fingerprint,
+                )
+            }
+            None => (
+                None,
+                None,
+                FileOpenState::Opening(open_file(path.clone())),
+                0,
+                Fingerprint::default(),
+            ),
+        };
         Ok(Self {
-            file: Some(file),
-            file_state: FileOpenState::Open,
+            file,
+            file_identity,
+            fingerprint,
+            next_file: None,
+            file_state,
+            state: FileState::Modified,
+            replaced: false,
             shared_state: shared_state.clone(),
             at_eof: false,
+            compare_contents: options.compare_contents,
+            on_delete: options.on_delete,
+            on_truncate: options.on_truncate,
+            debounce: options.debounce,
+            debounce_sleep: None,
+            debouncing: false,
+            events: broadcast::channel(EVENT_CAPACITY).0,
+            replacement_event: FileEvent::Rotated,
+            reported_deleted: false,
+            truncated: None,
+            #[cfg(feature = "tracing")]
+            span,
+            follow: options.follow,
+            follow_rotation: options.follow_rotation,
+            seek: None,
+            buffer: Vec::new(),
+            buffer_pos: 0,
+            buffer_filled: 0,
             _watcher: watcher,
-            // #[cfg(not(target_os = "windows"))]
-            path: path.as_ref().into(),
-            last_seek_location: 0,
+            path,
+            last_seek_location,
+            generation: 0,
+            history,
         })
     }
 }
@@ -0,0 +1,219 @@
+use crate::{Checkpoint, RecordBoundary, Records, Result, WatchedFile};
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use futures_core::Stream;
+use std::future::Future;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, ReadBuf};
+use tokio::time::Sleep;
+
+//how much we ask the file for at once.
+const READ_SIZE: usize = 8 * 1024;
+
+//how long a line can get by default before it's split, see `Lines::max_line_length`.
+const MAX_LINE_LENGTH: usize = 1024 * 1024;
+
+/// A line read from a [`WatchedFile`], without its line ending.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Line {
+    pub text: String,
+    /// Where the line starts in the file it was read from.
+    pub byte_offset: u64,
+    /// Counts the lines read from this generation, starting at 1.
+    pub line_number: u64,
+    pub path: Arc<Path>,
+    /// Goes up every time the file is rotated, recreated or truncated, `byte_offset` and
+    /// `line_number` start over when it does.
+    pub generation: u64,
+}
+
+/// A [`Stream`] of the [`Line`]s in a [`WatchedFile`], created with [`WatchedFile::lines`].
+///
+/// A line is only handed out once its newline was written, a trailing partial line is held back
+/// until then. It is handed out as is when the file gets rotated, truncated or deleted, when the
+/// stream ends, or after [`flush_partial_after`](Lines::flush_partial_after).
+///
+/// A line longer than [`max_line_length`](Lines::max_line_length) is split, so a file that never
+/// gets a newline doesn't pile up in memory.
+pub struct Lines {
+    file: WatchedFile,
+    path: Arc<Path>,
+    //bytes read from the file that haven't been handed out as a line yet, all from the same generation.
+    buf: Vec<u8>,
+    //where `buf` starts in the file.
+    buf_offset: u64,
+    generation: u64,
+    line_number: u64,
+    //where the last line we handed out ended, for checkpoints.
+    consumed: u64,
+    //where we start reading the file itself, for checkpoints taken while still in its rotated copies.
+    file_start: u64,
+    //everything up to here in `buf` has been searched for a newline already.
+    scanned: usize,
+    idle_timeout: Option<Duration>,
+    max_line_length: usize,
+    //runs while we hold back a partial line, restarted whenever more of it comes in.
+    idle: Option<Pin<Box<Sleep>>>,
+}
+
+impl Lines {
+    pub(crate) fn new(file: WatchedFile) -> Self {
+        Self {
+            path: file.current_path().into(),
+            buf: Vec::new(),
+            buf_offset: file.offset(),
+            generation: file.generation,
+            line_number: 0,
+            consumed: file.offset(),
+            file_start: file.last_seek_location,
+            scanned: 0,
+            idle_timeout: None,
+            max_line_length: MAX_LINE_LENGTH,
+            idle: None,
+            file,
+        }
+    }
+
+    /// Hand out a partial line once nothing was added to it for `timeout`, instead of waiting for
+    /// its newline. The rest of it comes as a line of its own.
+    pub fn flush_partial_after(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Hand out the first `max` bytes of a longer line as a line of their own, the rest comes after
+    /// it the same way. 1 MiB by default, the line ending doesn't count.
+    pub fn max_line_length(mut self, max: usize) -> Self {
+        self.max_line_length = max.max(1);
+        self
+    }
+
+    /// Join lines into multi-line records, like a log message followed by its stack trace.
+    pub fn records(self, boundary: RecordBoundary) -> Records {
+        Records::new(self, boundary)
+    }
+
+    /// Where the last line we returned ends, to resume right after it.
+    pub fn checkpoint(&self) -> Checkpoint {
+        let checkpoint = self.file.checkpoint();
+        Checkpoint {
+            offset: if *self.path != *self.file.path {
+                //nothing from the file itself was handed out yet.
+                self.file_start
+            } else if self.generation == self.file.generation {
+                self.consumed
+            } else {
+                //everything from the old file was handed out, nothing from the new one yet.
+                0
+            },
+            ..checkpoint
+        }
+    }
+
+    pub fn get_ref(&self) -> &WatchedFile {
+        &self.file
+    }
+
+    pub fn into_inner(self) -> WatchedFile {
+        self.file
+    }
+
+    //split the first `len` bytes of `buf` off as a line, dropping the line ending.
+    fn take_line(&mut self, len: usize) -> Line {
+        let rest = self.buf.split_off(len);
+        let mut bytes = std::mem::replace(&mut self.buf, rest);
+        if bytes.last() == Some(&b'\n') {
+            bytes.pop();
+            if bytes.last() == Some(&b'\r') {
+                bytes.pop();
+            }
+        }
+        let byte_offset = self.buf_offset;
+        self.buf_offset += len as u64;
+        self.consumed = self.buf_offset;
+        self.scanned = 0;
+        self.idle = None;
+        self.line_number += 1;
+        Line {
+            text: String::from_utf8_lossy(&bytes).into_owned(),
+            byte_offset,
+            line_number: self.line_number,
+            path: self.path.clone(),
+            generation: self.generation,
+        }
+    }
+}
+
+impl Stream for Lines {
+    type Item = Result<Line>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            let newline = this.buf[this.scanned..].iter().position(|b| *b == b'\n');
+            match newline.map(|newline| this.scanned + newline) {
+                Some(end) if end <= this.max_line_length => {
+                    return Poll::Ready(Some(Ok(this.take_line(end + 1))));
+                }
+                //the newline is further on than that, or isn't there yet but can't fit any more.
+                _ if this.buf.len() > this.max_line_length => {
+                    return Poll::Ready(Some(Ok(this.take_line(this.max_line_length))));
+                }
+                _ => {}
+            }
+            this.scanned = this.buf.len();
+            let filled = this.buf.len();
+            this.buf.resize(filled + READ_SIZE, 0);
+            let mut read_buf = ReadBuf::new(&mut this.buf[filled..]);
+            let poll = Pin::new(&mut this.file).poll_read(cx, &mut read_buf);
+            let read = read_buf.filled().len();
+            this.buf.truncate(filled + read);
+            match poll {
+                Poll::Pending if this.buf.is_empty() => return Poll::Pending,
+                Poll::Pending => {
+                    //nothing new yet and we're holding back a partial line.
+                    if this.file.reported_deleted || this.file.generation != this.generation {
+                        //the writer won't be finishing it.
+                        return Poll::Ready(Some(Ok(this.take_line(this.buf.len()))));
+                    }
+                    if let Some(timeout) = this.idle_timeout {
+                        let idle = this
+                            .idle
+                            .get_or_insert_with(|| Box::pin(tokio::time::sleep(timeout)));
+                        if idle.as_mut().poll(cx).is_ready() {
+                            return Poll::Ready(Some(Ok(this.take_line(this.buf.len()))));
+                        }
+                    }
+                    return Poll::Pending;
+                }
+                Poll::Ready(Err(e)) => return Poll::Ready(Some(Err(e.into()))),
+                Poll::Ready(Ok(())) if read == 0 => {
+                    //the end of the stream, whatever is left is the last line.
+                    return Poll::Ready(if this.buf.is_empty() {
+                        None
+                    } else {
+                        Some(Ok(this.take_line(this.buf.len())))
+                    });
+                }
+                Poll::Ready(Ok(())) => this.idle = None,
+            }
+            if this.file.generation != this.generation {
+                //we moved on to a new file (or the start of this one), the old one ended without a newline.
+                let start = this.file.offset() - read as u64;
+                let new = this.buf.split_off(filled);
+                let partial = (!this.buf.is_empty()).then(|| this.take_line(this.buf.len()));
+                this.buf = new;
+                this.buf_offset = start;
+                this.consumed = start;
+                this.generation = this.file.generation;
+                this.path = this.file.current_path().into();
+                this.line_number = 0;
+                if let Some(partial) = partial {
+                    return Poll::Ready(Some(Ok(partial)));
+                }
+            }
+        }
+    }
+}
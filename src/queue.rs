@@ -0,0 +1,54 @@
+use crate::PathEvent;
+use std::collections::VecDeque;
+
+//how many events can pile up between the watcher and the reader before we start folding them together.
+pub(crate) const QUEUE_CAPACITY: usize = 64;
+
+/*
+Events from the watcher, in the order they happened, waiting for the reader to pick them up.
+
+coalescing rules, none of which lose a delete or a rotation:
+    - the same event twice in a row is stored once, the second one says nothing new.
+    - a `Modified` right before a `Deleted` or `Replaced` is dropped, the reader drains the old file before acting on those anyway.
+    - once full, new `Modified` events are dropped. the queue isn't empty so the reader still gets woken up and checks the file.
+    - once full, a `Deleted` or `Replaced` folds the queue down to the last delete/rotation before it. the reader only ever
+      opens whatever lives at the path _now_, so the ones in between wouldn't have been seen anyway.
+*/
+pub(crate) struct EventQueue {
+    events: VecDeque<PathEvent>,
+}
+
+impl EventQueue {
+    pub(crate) fn new() -> Self {
+        Self {
+            events: VecDeque::with_capacity(QUEUE_CAPACITY),
+        }
+    }
+
+    pub(crate) fn push(&mut self, event: PathEvent) {
+        if self.events.back() == Some(&event) {
+            return;
+        }
+        if event != PathEvent::Modified && self.events.back() == Some(&PathEvent::Modified) {
+            self.events.pop_back();
+        }
+        if self.events.len() >= QUEUE_CAPACITY {
+            if event == PathEvent::Modified {
+                return;
+            }
+            let last = self
+                .events
+                .iter()
+                .rev()
+                .find(|e| **e != PathEvent::Modified)
+                .copied();
+            self.events.clear();
+            self.events.extend(last.filter(|last| *last != event));
+        }
+        self.events.push_back(event);
+    }
+
+    pub(crate) fn drain(&mut self) -> impl Iterator<Item = PathEvent> + '_ {
+        self.events.drain(..)
+    }
+}
@@ -0,0 +1,171 @@
+use crate::{Line, Lines, Result};
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use futures_core::Stream;
+use regex::Regex;
+use std::future::Future;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Sleep;
+
+const DEFAULT_MAX_LINES: usize = 500;
+const DEFAULT_FLUSH_AFTER: Duration = Duration::from_secs(5);
+
+/// How to tell which lines belong together.
+#[derive(Clone, Debug)]
+pub enum RecordBoundary {
+    /// Lines matching this start a new record, like a timestamp at the start of the line.
+    Start(Regex),
+    /// Lines matching this belong to the record before them, like `^\s` or `^Caused by:`.
+    Continuation(Regex),
+}
+
+impl RecordBoundary {
+    fn starts_record(&self, line: &str) -> bool {
+        match self {
+            RecordBoundary::Start(regex) => regex.is_match(line),
+            RecordBoundary::Continuation(regex) => !regex.is_match(line),
+        }
+    }
+}
+
+/// Lines that make up a single event, like a log message with its stack trace.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Record {
+    /// The lines joined with `\n`.
+    pub text: String,
+    /// Where the first line starts.
+    pub byte_offset: u64,
+    /// The line number of the first line.
+    pub line_number: u64,
+    pub line_count: usize,
+    pub path: Arc<Path>,
+    pub generation: u64,
+}
+
+impl From<Line> for Record {
+    fn from(line: Line) -> Self {
+        Record {
+            text: line.text,
+            byte_offset: line.byte_offset,
+            line_number: line.line_number,
+            line_count: 1,
+            path: line.path,
+            generation: line.generation,
+        }
+    }
+}
+
+/// A [`Stream`] of [`Record`]s, created with [`Lines::records`].
+///
+/// A record is handed out once the line starting the next one comes in, once it reaches
+/// [`max_lines`](Records::max_lines), once nothing was added to it for
+/// [`flush_after`](Records::flush_after), or when the file is rotated, truncated or the stream
+/// ends.
+pub struct Records {
+    lines: Lines,
+    boundary: RecordBoundary,
+    max_lines: usize,
+    flush_after: Option<Duration>,
+    //the record we're still adding lines to.
+    pending: Option<Record>,
+    idle: Option<Pin<Box<Sleep>>>,
+    done: bool,
+}
+
+impl Records {
+    pub(crate) fn new(lines: Lines, boundary: RecordBoundary) -> Self {
+        Self {
+            lines,
+            boundary,
+            max_lines: DEFAULT_MAX_LINES,
+            flush_after: Some(DEFAULT_FLUSH_AFTER),
+            pending: None,
+            idle: None,
+            done: false,
+        }
+    }
+
+    /// Hand out a record once it has this many lines, the next line starts a new one. 500 by default.
+    pub fn max_lines(mut self, max_lines: usize) -> Self {
+        self.max_lines = max_lines.max(1);
+        self
+    }
+
+    /// Hand out a record once nothing was added to it for this long, `None` to wait for the next
+    /// one to start. 5 seconds by default.
+    pub fn flush_after(mut self, timeout: Option<Duration>) -> Self {
+        self.flush_after = timeout;
+        self
+    }
+
+    pub fn get_ref(&self) -> &Lines {
+        &self.lines
+    }
+
+    pub fn into_inner(self) -> Lines {
+        self.lines
+    }
+
+    //add `line` to the pending record, returning the record it finished if any.
+    fn push(&mut self, line: Line) -> Option<Record> {
+        self.idle = None;
+        match &mut self.pending {
+            Some(pending)
+                if pending.generation == line.generation
+                    && !self.boundary.starts_record(&line.text) =>
+            {
+                pending.text.push('\n');
+                pending.text.push_str(&line.text);
+                pending.line_count += 1;
+                None
+            }
+            pending => pending.replace(line.into()),
+        }
+    }
+}
+
+impl Stream for Records {
+    type Item = Result<Record>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.done {
+            return Poll::Ready(None);
+        }
+        loop {
+            if this
+                .pending
+                .as_ref()
+                .is_some_and(|pending| pending.line_count >= this.max_lines)
+            {
+                return Poll::Ready(this.pending.take().map(Ok));
+            }
+            match Pin::new(&mut this.lines).poll_next(cx) {
+                Poll::Ready(Some(Ok(line))) => {
+                    if let Some(record) = this.push(line) {
+                        return Poll::Ready(Some(Ok(record)));
+                    }
+                }
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                Poll::Ready(None) => {
+                    this.done = true;
+                    return Poll::Ready(this.pending.take().map(Ok));
+                }
+                Poll::Pending => {
+                    if let (Some(_), Some(timeout)) = (&this.pending, this.flush_after) {
+                        let idle = this
+                            .idle
+                            .get_or_insert_with(|| Box::pin(tokio::time::sleep(timeout)));
+                        if idle.as_mut().poll(cx).is_ready() {
+                            this.idle = None;
+                            return Poll::Ready(this.pending.take().map(Ok));
+                        }
+                    }
+                    return Poll::Pending;
+                }
+            }
+        }
+    }
+}
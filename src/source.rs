@@ -0,0 +1,120 @@
+use crate::Result;
+use notify::Event;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Called with every event in a watched directory, or with the error when watching it failed.
+pub type EventCallback = Box<dyn FnMut(notify::Result<Event>) + Send>;
+
+/// Where a [`WatchedFile`](crate::WatchedFile) hears about changes to the directory its file lives
+/// in. By default every file starts a notify watcher of its own, a [`WatchHub`](crate::WatchHub)
+/// shares one, and with the `testing` feature `testing::FakeWatcher` only reports what a test
+/// tells it to.
+///
+/// Set with [`WatchedFileBuilder::source`](crate::WatchedFileBuilder::source).
+pub trait WatchSource: Send + Sync {
+    /// Call `callback` with the events about `dir` and the files directly in it, until the
+    /// returned guard is dropped. Paths in the events are `dir` joined with the file name, `dir`
+    /// is always canonical.
+    fn watch(&self, dir: &Path, callback: EventCallback) -> Result<WatchGuard>;
+}
+
+/// Keeps a directory watched by a [`WatchSource`], dropping it stops the events.
+pub struct WatchGuard {
+    _inner: Box<dyn Send>,
+}
+
+impl WatchGuard {
+    /// A guard that drops `inner` when it's dropped itself.
+    pub fn new(inner: impl Send + 'static) -> Self {
+        Self {
+            _inner: Box::new(inner),
+        }
+    }
+}
+
+//who wants to hear about which directory.
+#[derive(Default)]
+pub(crate) struct Router {
+    routes: HashMap<PathBuf, Vec<(u64, EventCallback)>>,
+    next_id: u64,
+}
+
+impl Router {
+    //the id to remove `callback` with again, and whether it's the first one for `dir`.
+    pub(crate) fn add(&mut self, dir: &Path, callback: EventCallback) -> (u64, bool) {
+        self.next_id += 1;
+        let callbacks = self.routes.entry(dir.to_path_buf()).or_default();
+        callbacks.push((self.next_id, callback));
+        (self.next_id, callbacks.len() == 1)
+    }
+
+    //whether it was the last one for `dir`.
+    pub(crate) fn remove(&mut self, dir: &Path, id: u64) -> bool {
+        let Some(callbacks) = self.routes.get_mut(dir) else {
+            return false;
+        };
+        callbacks.retain(|(other, _)| *other != id);
+        let last = callbacks.is_empty();
+        if last {
+            self.routes.remove(dir);
+        }
+        last
+    }
+
+    #[cfg(feature = "testing")]
+    pub(crate) fn is_watched(&self, dir: &Path) -> bool {
+        self.routes.contains_key(dir)
+    }
+
+    //hand an event to everyone watching the directory it happened in, looked up by its paths so
+    // the other directories don't cost anything.
+    pub(crate) fn route(&mut self, res: notify::Result<Event>) {
+        match res {
+            Ok(event) => {
+                for dir in dirs(&event.paths) {
+                    if let Some(callbacks) = self.routes.get_mut(dir) {
+                        for (_, callback) in callbacks.iter_mut() {
+                            callback(Ok(event.clone()));
+                        }
+                    }
+                }
+            }
+            //notify errors can't be cloned, so everyone gets their own copy of the message.
+            Err(e) if e.paths.is_empty() => {
+                for (dir, callbacks) in self.routes.iter_mut() {
+                    for (_, callback) in callbacks.iter_mut() {
+                        callback(Err(
+                            notify::Error::generic(&e.to_string()).add_path(dir.clone())
+                        ));
+                    }
+                }
+            }
+            Err(e) => {
+                for dir in dirs(&e.paths) {
+                    if let Some(callbacks) = self.routes.get_mut(dir) {
+                        for (_, callback) in callbacks.iter_mut() {
+                            callback(Err(
+                                notify::Error::generic(&e.to_string()).add_path(dir.to_path_buf())
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+//the directories `paths` are in, and the paths themselves in case they're watched directories.
+// every one only once, so a rename within a directory is only reported once.
+fn dirs(paths: &[PathBuf]) -> Vec<&Path> {
+    let mut dirs = Vec::new();
+    for path in paths {
+        for dir in [Some(path.as_path()), path.parent()].into_iter().flatten() {
+            if !dirs.contains(&dir) {
+                dirs.push(dir);
+            }
+        }
+    }
+    dirs
+}
@@ -0,0 +1,272 @@
+//! Driving a [`WatchedFile`] through file changes one step at a time, without
+//! a real watcher or sleeping in between.
+//!
+//! A [`Scenario`] changes a file in a scratch directory and then reports the change through a
+//! [`FakeWatcher`], the same events a real watcher would have sent. Nothing happens between the
+//! steps, so with `tokio::time::pause` a test can check what the file reads after each one and a
+//! `timeout` around a read that should stay pending elapses right away.
+use crate::source::{EventCallback, Router, WatchGuard, WatchSource};
+use crate::{Backend, Handler, Result, WatchHub, WatchedFile, WatchedFileBuilder};
+use notify::event::{CreateKind, DataChange, ModifyKind, RemoveKind, RenameMode};
+use notify::{Event, EventHandler, EventKind, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::io::AsyncWriteExt;
+
+/// Make the native watches of `builder` fail with
+/// [`MaxFilesWatch`](notify::ErrorKind::MaxFilesWatch), like they do once the inotify watch limit
+/// is used up. [`Backend::Auto`] falls back to polling then.
+///
+/// A [`hub`](WatchedFileBuilder::hub) brings its own watcher, see [`exhausted_hub`] for that.
+pub fn exhaust_native_watches(builder: WatchedFileBuilder) -> WatchedFileBuilder {
+    builder.native_watcher(exhausted_watcher)
+}
+
+/// A [`WatchHub`] for `backend` whose native watches fail, like with [`exhaust_native_watches`].
+pub fn exhausted_hub(backend: Backend) -> Result<WatchHub> {
+    WatchHub::with_native_watcher(backend, exhausted_watcher)
+}
+
+fn exhausted_watcher(handler: Handler) -> notify::Result<Box<dyn Watcher + Send>> {
+    Ok(Box::new(ExhaustedWatcher::new(handler)?))
+}
+
+//a native watcher that can't take any more watches.
+struct ExhaustedWatcher(notify::RecommendedWatcher);
+
+impl Watcher for ExhaustedWatcher {
+    fn new<F: EventHandler>(event_handler: F) -> notify::Result<Self> {
+        Ok(Self(notify::recommended_watcher(event_handler)?))
+    }
+
+    fn watch(&mut self, _path: &Path, _recursive_mode: RecursiveMode) -> notify::Result<()> {
+        Err(notify::Error::new(notify::ErrorKind::MaxFilesWatch))
+    }
+
+    fn unwatch(&mut self, path: &Path) -> notify::Result<()> {
+        self.0.unwatch(path)
+    }
+
+    fn configure(&mut self, option: notify::Config) -> notify::Result<bool> {
+        self.0.configure(option)
+    }
+
+    fn kind() -> notify::WatcherKind {
+        notify::RecommendedWatcher::kind()
+    }
+}
+
+/// A [`WatchSource`] that only reports the events it's handed.
+#[derive(Clone, Default)]
+pub struct FakeWatcher {
+    router: Arc<Mutex<Router>>,
+}
+
+impl FakeWatcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hand `event` to everyone watching the directory it happened in.
+    pub fn emit(&self, event: Event) {
+        self.router.lock().unwrap().route(Ok(event));
+    }
+
+    /// Report `error` to everyone watching the directory it's about, or to everyone if it has no
+    /// paths.
+    pub fn fail(&self, error: notify::Error) {
+        self.router.lock().unwrap().route(Err(error));
+    }
+
+    /// Whether anyone is still watching `dir`.
+    pub fn is_watching(&self, dir: &Path) -> bool {
+        self.router.lock().unwrap().is_watched(dir)
+    }
+}
+
+impl WatchSource for FakeWatcher {
+    fn watch(&self, dir: &Path, callback: EventCallback) -> Result<WatchGuard> {
+        let (id, _) = self.router.lock().unwrap().add(dir, callback);
+        Ok(WatchGuard::new(Registration {
+            router: self.router.clone(),
+            dir: dir.to_path_buf(),
+            id,
+        }))
+    }
+}
+
+struct Registration {
+    router: Arc<Mutex<Router>>,
+    dir: PathBuf,
+    id: u64,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        self.router.lock().unwrap().remove(&self.dir, self.id);
+    }
+}
+
+/// A file in a scratch directory of its own, changed one step at a time. Every step changes the
+/// file on disk first and then reports it through [`watcher`](Scenario::watcher).
+///
+/// The directory is removed again when the scenario is dropped.
+pub struct Scenario {
+    dir: PathBuf,
+    path: PathBuf,
+    watcher: FakeWatcher,
+}
+
+impl Scenario {
+    /// An empty file called `name`, in a directory nothing else uses.
+    pub async fn new(name: &str) -> std::io::Result<Self> {
+        let dir = std::env::temp_dir().join(format!("tokio-watch-{}-{}", std::process::id(), name));
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+        tokio::fs::create_dir_all(&dir).await?;
+        //watched files canonicalize their directory, the events have to match it.
+        let dir = tokio::fs::canonicalize(&dir).await?;
+        let path = dir.join(name);
+        tokio::fs::File::create(&path).await?;
+        Ok(Self {
+            dir,
+            path,
+            watcher: FakeWatcher::new(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn watcher(&self) -> &FakeWatcher {
+        &self.watcher
+    }
+
+    /// A builder for the file that hears about changes from this scenario only.
+    pub fn builder(&self) -> WatchedFileBuilder {
+        WatchedFile::builder(&self.path).source(self.watcher.clone())
+    }
+
+    /// The `n`th rotated copy, like `name.1` for the newest one.
+    pub fn rotated(&self, n: usize) -> PathBuf {
+        rotated(&self.path, n)
+    }
+
+    /// Write `text` to the end of the file.
+    pub async fn append(&self, text: &str) -> std::io::Result<()> {
+        append(&self.path, text).await?;
+        self.emit(
+            EventKind::Modify(ModifyKind::Data(DataChange::Content)),
+            &[&self.path],
+        );
+        Ok(())
+    }
+
+    /// Write `text` to the end of the `n`th rotated copy, like a writer that still has the file
+    /// open after it was rotated away.
+    pub async fn append_rotated(&self, n: usize, text: &str) -> std::io::Result<()> {
+        let rotated = self.rotated(n);
+        append(&rotated, text).await?;
+        self.emit(
+            EventKind::Modify(ModifyKind::Data(DataChange::Content)),
+            &[&rotated],
+        );
+        Ok(())
+    }
+
+    /// Cut the file down to nothing, keeping the same file.
+    pub async fn truncate(&self) -> std::io::Result<()> {
+        tokio::fs::OpenOptions::new()
+            .write(true)
+            .truncate(true)
+            .open(&self.path)
+            .await?;
+        self.emit(
+            EventKind::Modify(ModifyKind::Data(DataChange::Size)),
+            &[&self.path],
+        );
+        Ok(())
+    }
+
+    /// Rotate like logrotate's default: the older copies move up one, the file is renamed to
+    /// `name.1` and a new empty file takes its place.
+    pub async fn rotate(&self) -> std::io::Result<()> {
+        self.shift().await?;
+        let rotated = self.rotated(1);
+        tokio::fs::rename(&self.path, &rotated).await?;
+        self.emit(
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)),
+            &[&self.path, &rotated],
+        );
+        self.recreate().await
+    }
+
+    /// Rotate like logrotate's `copytruncate`: the file is copied to `name.1` and then truncated,
+    /// the writer keeps the same file.
+    pub async fn copy_truncate(&self) -> std::io::Result<()> {
+        self.shift().await?;
+        let rotated = self.rotated(1);
+        tokio::fs::copy(&self.path, &rotated).await?;
+        self.emit(EventKind::Create(CreateKind::File), &[&rotated]);
+        self.truncate().await
+    }
+
+    /// Remove the file.
+    pub async fn delete(&self) -> std::io::Result<()> {
+        tokio::fs::remove_file(&self.path).await?;
+        self.emit(EventKind::Remove(RemoveKind::File), &[&self.path]);
+        Ok(())
+    }
+
+    /// Create a new empty file at the path, after it was deleted or rotated away.
+    pub async fn recreate(&self) -> std::io::Result<()> {
+        tokio::fs::File::create(&self.path).await?;
+        self.emit(EventKind::Create(CreateKind::File), &[&self.path]);
+        Ok(())
+    }
+
+    //move `name.n` to `name.n+1`, starting with the oldest, to make room for a new `name.1`.
+    async fn shift(&self) -> std::io::Result<()> {
+        let mut oldest = 0;
+        while tokio::fs::try_exists(self.rotated(oldest + 1)).await? {
+            oldest += 1;
+        }
+        for n in (1..=oldest).rev() {
+            let (from, to) = (self.rotated(n), self.rotated(n + 1));
+            tokio::fs::rename(&from, &to).await?;
+            self.emit(
+                EventKind::Modify(ModifyKind::Name(RenameMode::Both)),
+                &[&from, &to],
+            );
+        }
+        Ok(())
+    }
+
+    fn emit(&self, kind: EventKind, paths: &[&Path]) {
+        let event = paths.iter().fold(Event::new(kind), |event, path| {
+            event.add_path(path.to_path_buf())
+        });
+        self.watcher.emit(event);
+    }
+}
+
+impl Drop for Scenario {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+async fn append(path: &Path, text: &str) -> std::io::Result<()> {
+    let mut file = tokio::fs::OpenOptions::new()
+        .append(true)
+        .open(path)
+        .await?;
+    file.write_all(text.as_bytes()).await?;
+    file.sync_data().await
+}
+
+fn rotated(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}", n));
+    path.with_file_name(name)
+}
@@ -0,0 +1,227 @@
+use crate::file_set::FileSet;
+use crate::{
+    recommended_watcher, watch_dir, Backend, Error, Line, Result, StartPosition, WatchHub,
+};
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use futures_core::Stream;
+use notify::event::ModifyKind;
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use std::future::Future;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc;
+
+type ScanFuture = Pin<Box<dyn Future<Output = std::io::Result<Vec<PathBuf>>> + Send>>;
+
+/// Every regular file under a directory, including subdirectories that show up later, as one
+/// [`Stream`] of lines tagged with the file they came from. Created with [`WatchedDir::builder`].
+///
+/// Files that start existing later are read from the start. A file drops out once it was deleted
+/// and read to the end, or after it returned an error. A file is only read once, also when it
+/// gets renamed within the directory, like `app.log` to `app.log.1` by logrotate.
+pub struct WatchedDir {
+    root: PathBuf,
+    //what notify calls `root`, to map the paths in its events back.
+    canonical_root: PathBuf,
+    filter: Filter,
+    files: FileSet,
+    //new files and directories we're still looking through.
+    scanning: Vec<ScanFuture>,
+    created: mpsc::UnboundedReceiver<notify::Result<PathBuf>>,
+    //only here to tie the lifetimes together
+    _watcher: Box<dyn Watcher + Send>,
+}
+
+/// Options for a [`WatchedDir`], created with [`WatchedDir::builder`].
+pub struct WatchedDirBuilder {
+    root: PathBuf,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    start: StartPosition,
+    backend: Backend,
+}
+
+impl WatchedDirBuilder {
+    /// Only follow files whose path relative to the directory matches this glob pattern, `*`
+    /// matches across directories. Can be given more than once, by default every file is followed.
+    pub fn include(mut self, pattern: impl Into<String>) -> Self {
+        self.include.push(pattern.into());
+        self
+    }
+    /// Skip files whose path relative to the directory matches this glob pattern, even if they
+    /// match an `include`.
+    pub fn exclude(mut self, pattern: impl Into<String>) -> Self {
+        self.exclude.push(pattern.into());
+        self
+    }
+    /// Where to start reading the files that already exist, new files are always read from the start.
+    pub fn start(mut self, start: StartPosition) -> Self {
+        self.start = start;
+        self
+    }
+    /// How we find out about new files and changes to them, for the directory and every file in it.
+    pub fn backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+    pub async fn build(self) -> Result<WatchedDir> {
+        let filter = Filter {
+            include: parse_patterns(&self.include)?,
+            exclude: parse_patterns(&self.exclude)?,
+        };
+        let canonical_root = tokio::fs::canonicalize(&self.root).await?;
+
+        let (tx, created) = mpsc::unbounded_channel();
+        let handler = move |res: notify::Result<Event>| match res {
+            //a file or directory showing up under a name, either created or renamed to it.
+            Ok(event)
+                if matches!(
+                    event.kind,
+                    EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(_))
+                ) =>
+            {
+                for path in event.paths {
+                    let _ = tx.send(Ok(path));
+                }
+            }
+            Ok(_) => {}
+            Err(e) => {
+                let _ = tx.send(Err(e));
+            }
+        };
+        let watcher = watch_dir(
+            self.backend,
+            recommended_watcher,
+            handler,
+            &canonical_root,
+            RecursiveMode::Recursive,
+        )?;
+
+        let mut this = WatchedDir {
+            root: self.root,
+            canonical_root,
+            filter,
+            files: FileSet::new(WatchHub::with_backend(self.backend)?),
+            scanning: Vec::new(),
+            created,
+            _watcher: watcher,
+        };
+        //the watcher is already running, so anything created from here on is seen by one or the other.
+        for path in scan(this.root.clone()).await? {
+            this.follow(path, self.start);
+        }
+        Ok(this)
+    }
+}
+
+fn parse_patterns(patterns: &[String]) -> Result<Vec<glob::Pattern>> {
+    patterns
+        .iter()
+        .map(|pattern| {
+            glob::Pattern::new(pattern)
+                .map_err(|e| Error::Io(std::io::Error::new(ErrorKind::InvalidInput, e.msg)))
+        })
+        .collect()
+}
+
+struct Filter {
+    include: Vec<glob::Pattern>,
+    exclude: Vec<glob::Pattern>,
+}
+
+impl Filter {
+    fn matches(&self, relative: &Path) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|p| p.matches_path(relative)))
+            && !self.exclude.iter().any(|p| p.matches_path(relative))
+    }
+}
+
+//every regular file at or below `path`. symlinks are skipped, so we can't end up going in circles.
+fn scan(path: PathBuf) -> ScanFuture {
+    Box::pin(async move {
+        let mut files = Vec::new();
+        let mut dirs = vec![path];
+        while let Some(path) = dirs.pop() {
+            let metadata = match tokio::fs::symlink_metadata(&path).await {
+                Ok(metadata) => metadata,
+                //gone again before we got to it.
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            };
+            if metadata.is_file() {
+                files.push(path);
+            } else if metadata.is_dir() {
+                let mut entries = match tokio::fs::read_dir(&path).await {
+                    Ok(entries) => entries,
+                    Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                    Err(e) => return Err(e),
+                };
+                while let Some(entry) = entries.next_entry().await? {
+                    dirs.push(entry.path());
+                }
+            }
+        }
+        Ok(files)
+    })
+}
+
+impl WatchedDir {
+    pub fn builder(root: impl AsRef<Path>) -> WatchedDirBuilder {
+        WatchedDirBuilder {
+            root: root.as_ref().into(),
+            include: Vec::new(),
+            exclude: Vec::new(),
+            start: StartPosition::Start,
+            backend: Backend::Native,
+        }
+    }
+
+    /// Follow every file under `root`, reading the ones that already exist from the start.
+    pub async fn new(root: impl AsRef<Path>) -> Result<Self> {
+        Self::builder(root).build().await
+    }
+
+    fn follow(&mut self, path: PathBuf, start: StartPosition) {
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return;
+        };
+        if self.filter.matches(relative) {
+            self.files.open(path, start);
+        }
+    }
+}
+
+impl Stream for WatchedDir {
+    type Item = Result<(PathBuf, Line)>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        while let Poll::Ready(Some(created)) = this.created.poll_recv(cx) {
+            match created {
+                Ok(path) => {
+                    if let Ok(relative) = path.strip_prefix(&this.canonical_root) {
+                        //a new directory might have files in it already, before notify started watching it.
+                        // a file renamed within the tree is recognized by `FileSet`, so it isn't read twice.
+                        this.scanning.push(scan(this.root.join(relative)));
+                    }
+                }
+                Err(e) => return Poll::Ready(Some(Err(e.into()))),
+            }
+        }
+        let mut i = 0;
+        while i < this.scanning.len() {
+            match this.scanning[i].as_mut().poll(cx) {
+                Poll::Pending => i += 1,
+                Poll::Ready(result) => {
+                    drop(this.scanning.swap_remove(i));
+                    for path in result? {
+                        this.follow(path, StartPosition::Start);
+                    }
+                }
+            }
+        }
+        //we keep watching the directory for new files, so this never ends.
+        this.files.poll_next(cx).map(Some)
+    }
+}
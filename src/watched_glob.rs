@@ -0,0 +1,129 @@
+use crate::file_set::FileSet;
+use crate::{Error, Line, Result, StartPosition, WatchGuard, WatchHub, WatchSource};
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use futures_core::Stream;
+use notify::event::ModifyKind;
+use notify::{Event, EventKind};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc;
+
+/// Every file in a directory whose name matches a glob pattern, like `/var/log/*.log`, as one
+/// [`Stream`] of lines tagged with the file they came from.
+///
+/// Files that exist when the stream is created are picked up right away, files that start
+/// matching later are read from the start. A file drops out once it was deleted and read to the
+/// end, or after it returned an error.
+///
+/// A file is only read once, also when it gets renamed to another name that matches, like
+/// `app.log` to `app.log.1` with a pattern of `app.log*`.
+///
+/// Only the file name can contain wildcards, the directory has to exist. Matching directories
+/// and anything else that isn't a regular file are skipped.
+pub struct WatchedGlob {
+    dir: PathBuf,
+    pattern: glob::Pattern,
+    files: FileSet,
+    created: mpsc::UnboundedReceiver<notify::Result<PathBuf>>,
+    //only here to tie the lifetimes together
+    _guard: WatchGuard,
+}
+
+impl WatchedGlob {
+    /// Read the files that already match from the start.
+    pub async fn new(pattern: &str) -> Result<Self> {
+        Self::with_start(pattern, StartPosition::Start).await
+    }
+
+    /// Only read what gets written to the files that already match from now on.
+    pub async fn tail(pattern: &str) -> Result<Self> {
+        Self::with_start(pattern, StartPosition::End).await
+    }
+
+    async fn with_start(pattern: &str, start: StartPosition) -> Result<Self> {
+        let path = Path::new(pattern);
+        let invalid = |msg| Error::Io(std::io::Error::new(ErrorKind::InvalidInput, msg));
+        let file_pattern = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| invalid("pattern does not end in a file name"))?;
+        let pattern = glob::Pattern::new(file_pattern).map_err(|e| invalid(e.msg))?;
+        let dir = match path.parent() {
+            Some(parent) if parent != Path::new("") => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        if dir
+            .to_str()
+            .is_some_and(|dir| dir.contains(['*', '?', '[']))
+        {
+            return Err(invalid("only the file name can contain wildcards"));
+        }
+
+        let (tx, created) = mpsc::unbounded_channel();
+        let handler = move |res: notify::Result<Event>| match res {
+            //a file showing up under a name, either created or renamed to it.
+            Ok(event)
+                if matches!(
+                    event.kind,
+                    EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(_))
+                ) =>
+            {
+                for path in event.paths {
+                    let _ = tx.send(Ok(path));
+                }
+            }
+            Ok(_) => {}
+            Err(e) => {
+                let _ = tx.send(Err(e));
+            }
+        };
+        //the files are watched through the same hub, so it has to be the same path they use.
+        let hub = WatchHub::new()?;
+        let guard = hub.watch(&tokio::fs::canonicalize(&dir).await?, Box::new(handler))?;
+
+        let mut this = Self {
+            dir,
+            pattern,
+            files: FileSet::new(hub),
+            created,
+            _guard: guard,
+        };
+        //the watcher is already running, so anything created from here on is seen by one or the other.
+        let mut entries = tokio::fs::read_dir(&this.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if let Some(path) = this.matching(&entry.path()) {
+                this.files.open(path, start);
+            }
+        }
+        Ok(this)
+    }
+
+    //the path we should follow for `path`, if its name matches.
+    fn matching(&self, path: &Path) -> Option<PathBuf> {
+        let name = path.file_name()?;
+        self.pattern
+            .matches(name.to_str()?)
+            .then(|| self.dir.join(name))
+    }
+}
+
+impl Stream for WatchedGlob {
+    type Item = Result<(PathBuf, Line)>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        while let Poll::Ready(Some(created)) = this.created.poll_recv(cx) {
+            match created {
+                Ok(path) => {
+                    if let Some(path) = this.matching(&path) {
+                        this.files.open(path, StartPosition::Start);
+                    }
+                }
+                Err(e) => return Poll::Ready(Some(Err(e.into()))),
+            }
+        }
+        //we keep watching the directory for new files, so this never ends.
+        this.files.poll_next(cx).map(Some)
+    }
+}
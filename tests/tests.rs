@@ -1,8 +1,15 @@
+use futures_util::StreamExt;
 use std::io::SeekFrom;
 use std::path::Path;
 use tokio::fs::OpenOptions;
 use tokio::io::{AsyncBufReadExt, AsyncSeekExt, AsyncWriteExt, BufReader};
-use tokio_watch::{Result, WatchedFile};
+use tokio_watch::testing::Scenario;
+use tokio_watch::{
+    Backend, Checkpoint, DeletePolicy, Error, FileEvent, Lines, RecordBoundary, Records,
+    StartPosition, TruncatePolicy, WatchHub, WatchedDir, WatchedFile, WatchedGlob,
+};
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
 
 async fn write_test_file(path: impl AsRef<Path>, append: bool, n_lines: usize) -> Result<()> {
     let mut file = OpenOptions::new()
@@ -22,6 +29,11 @@ async fn write_test_file(path: impl AsRef<Path>, append: bool, n_lines: usize) -
     Ok(())
 }
 
+//"Line 0\n" up to "Line {n - 1}\n", like `write_test_file` writes.
+fn numbered(n_lines: usize) -> String {
+    (0..n_lines).map(|i| format!("Line {}\n", i)).collect()
+}
+
 async fn touch(path: impl AsRef<Path>) -> Result<()> {
     //make sure the file exists and is cleared.
     OpenOptions::new()
@@ -34,75 +46,1439 @@ async fn touch(path: impl AsRef<Path>) -> Result<()> {
     Ok(())
 }
 
-#[tokio::test]
+#[tokio::test(start_paused = true)]
 async fn simple_read() -> Result<()> {
-    touch("simple_read").await?;
-    tokio::spawn(async move {
-        write_test_file("simple_read", false, 5).await?;
+    let scenario = Scenario::new("simple_read").await?;
+    let mut file = BufReader::new(scenario.builder().build().await?).lines();
+    for i in 0..5 {
+        scenario.append(&format!("Line {}\n", i)).await?;
+    }
+    scenario.delete().await?;
+    let mut lines = Vec::new();
+    while let Some(line) = next_line(&mut file).await? {
+        lines.push(line);
+    }
+    let expected = vec!["Line 0", "Line 1", "Line 2", "Line 3", "Line 4"];
+    assert_eq!(lines, expected);
+    Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn truncate() -> Result<()> {
+    let scenario = Scenario::new("truncate").await?;
+    let mut file = BufReader::new(scenario.builder().build().await?).lines();
+    let mut lines = Vec::new();
+    scenario.append("Line 0\nLine 1\nLine 2\n").await?;
+    for _ in 0..3 {
+        lines.extend(next_line(&mut file).await?);
+    }
+    //shorter than what we read, so it's told apart from more being written.
+    scenario.truncate().await?;
+    scenario.append("Line 0\nLine 1\n").await?;
+    for _ in 0..2 {
+        lines.extend(next_line(&mut file).await?);
+    }
+    scenario.delete().await?;
+    assert_eq!(next_line(&mut file).await?, None);
+    let expected = vec!["Line 0", "Line 1", "Line 2", "Line 0", "Line 1"];
+    assert_eq!(lines, expected);
+    Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn delete_while_read() -> Result<()> {
+    let scenario = Scenario::new("delete_while_read").await?;
+    scenario.append(&numbered(5)).await?;
+    let mut file = BufReader::new(scenario.builder().build().await?).lines();
+    let mut lines = Vec::new();
+    for _ in 0..3 {
+        lines.extend(next_line(&mut file).await?);
+    }
+    scenario.delete().await?;
+    while let Some(line) = next_line(&mut file).await? {
+        lines.push(line);
+    }
+    let expected = vec!["Line 0", "Line 1", "Line 2", "Line 3", "Line 4"];
+    assert_eq!(lines, expected);
+    Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn rename_rotate() -> Result<()> {
+    let scenario = Scenario::new("rename_rotate").await?;
+    let mut file = BufReader::new(scenario.builder().build().await?).lines();
+    let mut lines = Vec::new();
+    scenario.append("Line 0\nLine 1\nLine 2\n").await?;
+    for _ in 0..3 {
+        lines.extend(next_line(&mut file).await?);
+    }
+    //rotate the file away, the writer still has it open and keeps appending for a bit
+    scenario.rotate().await?;
+    scenario.append_rotated(1, "Line 0\nLine 1\n").await?;
+    for _ in 0..2 {
+        lines.extend(next_line(&mut file).await?);
+    }
+    //and then starts over at the original path
+    scenario.append("Line 0\nLine 1\nLine 2\nLine 3\n").await?;
+    for _ in 0..4 {
+        lines.extend(next_line(&mut file).await?);
+    }
+    scenario.delete().await?;
+    assert_eq!(next_line(&mut file).await?, None);
+    let expected = vec![
+        "Line 0", "Line 1", "Line 2", "Line 0", "Line 1", "Line 0", "Line 1", "Line 2", "Line 3",
+    ];
+    assert_eq!(lines, expected);
+    Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn wait_for_missing() -> Result<()> {
+    let scenario = Scenario::new("wait_for_missing").await?;
+    scenario.delete().await?;
+    let file = scenario.builder().wait_for_creation(true).build().await?;
+    let mut file = BufReader::new(file).lines();
+    assert!(nothing_yet(&mut file).await);
+    scenario.recreate().await?;
+    scenario.append(&numbered(3)).await?;
+    let mut lines = Vec::new();
+    for _ in 0..3 {
+        lines.extend(next_line(&mut file).await?);
+    }
+    scenario.delete().await?;
+    assert_eq!(next_line(&mut file).await?, None);
+    let expected = vec!["Line 0", "Line 1", "Line 2"];
+    assert_eq!(lines, expected);
+    Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn start_last_lines() -> Result<()> {
+    let scenario = Scenario::new("start_last_lines").await?;
+    scenario.append(&numbered(5)).await?;
+    let file = scenario
+        .builder()
+        .start(StartPosition::LastLines(2))
+        .build()
+        .await?;
+    let mut file = BufReader::new(file).lines();
+    let mut lines = Vec::new();
+    for _ in 0..2 {
+        lines.extend(next_line(&mut file).await?);
+    }
+    scenario.append(&numbered(1)).await?;
+    lines.extend(next_line(&mut file).await?);
+    scenario.delete().await?;
+    assert_eq!(next_line(&mut file).await?, None);
+    let expected = vec!["Line 3", "Line 4", "Line 0"];
+    assert_eq!(lines, expected);
+    Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn wait_for_recreation() -> Result<()> {
+    let scenario = Scenario::new("wait_for_recreation").await?;
+    let file = scenario
+        .builder()
+        .on_delete(DeletePolicy::WaitForRecreation)
+        .build()
+        .await?;
+    let mut file = BufReader::new(file).lines();
+    let mut lines = Vec::new();
+    scenario.append("Line 0\nLine 1\n").await?;
+    for _ in 0..2 {
+        lines.extend(next_line(&mut file).await?);
+    }
+    scenario.delete().await?;
+    //we never see EOF with this policy, the file just stays pending until it's back.
+    assert!(nothing_yet(&mut file).await);
+    scenario.recreate().await?;
+    scenario.append("Line 0\nLine 1\nLine 2\n").await?;
+    for _ in 0..3 {
+        lines.extend(next_line(&mut file).await?);
+    }
+    let expected = vec!["Line 0", "Line 1", "Line 0", "Line 1", "Line 2"];
+    assert_eq!(lines, expected);
+    Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn truncate_error() -> Result<()> {
+    let scenario = Scenario::new("truncate_error").await?;
+    let file = scenario
+        .builder()
+        .on_truncate(TruncatePolicy::Error)
+        .build()
+        .await?;
+    let mut file = BufReader::new(file).lines();
+    scenario.append(&numbered(5)).await?;
+    for _ in 0..5 {
+        next_line(&mut file).await?;
+    }
+    scenario.truncate().await?;
+    scenario.append(&numbered(1)).await?;
+    let first = file.next_line().await.map_err(Error::from);
+    //the error sticks, also once the file grows past where we were.
+    let second = file.next_line().await.map_err(Error::from);
+    scenario.append(&numbered(8)).await?;
+    let regrown = file.next_line().await.map_err(Error::from);
+    for result in [first, second, regrown] {
+        assert!(matches!(
+            result,
+            Err(Error::Truncated {
+                old_len: 35,
+                new_len: 7
+            })
+        ));
+    }
+    Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn file_events() -> Result<()> {
+    let scenario = Scenario::new("file_events").await?;
+    let file = scenario.builder().build().await?;
+    let mut events = file.events();
+    let mut file = BufReader::new(file).lines();
+    let mut lines = Vec::new();
+    scenario.append("Line 0\nLine 1\n").await?;
+    for _ in 0..2 {
+        lines.extend(next_line(&mut file).await?);
+    }
+    scenario.truncate().await?;
+    scenario.append("Line 0\n").await?;
+    lines.extend(next_line(&mut file).await?);
+    scenario.rotate().await?;
+    scenario.append("Line 0\n").await?;
+    lines.extend(next_line(&mut file).await?);
+    scenario.delete().await?;
+    assert_eq!(next_line(&mut file).await?, None);
+    assert_eq!(lines, vec!["Line 0", "Line 1", "Line 0", "Line 0"]);
+    let mut seen = Vec::new();
+    while let Ok(event) = events.try_recv() {
+        seen.push(event);
+    }
+    assert_eq!(
+        seen,
+        [
+            FileEvent::Truncated {
+                old_len: 14,
+                new_len: 7
+            },
+            FileEvent::Rotated,
+            FileEvent::Deleted
+        ]
+    );
+    Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn resume_checkpoint() -> Result<()> {
+    let scenario = Scenario::new("resume_checkpoint").await?;
+    scenario.append(&numbered(3)).await?;
+    let mut file = BufReader::new(scenario.builder().build().await?).lines();
+    for _ in 0..3 {
+        next_line(&mut file).await?;
+    }
+    //what would get written to disk before shutting down
+    let saved = file.get_ref().get_ref().checkpoint().to_string();
+    drop(file);
+    let checkpoint: Checkpoint = saved.parse()?;
+    assert_eq!(checkpoint.offset, 21);
+
+    scenario.append(&numbered(2)).await?;
+    let file = scenario
+        .builder()
+        .start(StartPosition::Checkpoint(checkpoint))
+        .build()
+        .await?;
+    let mut file = BufReader::new(file).lines();
+    let mut lines = Vec::new();
+    for _ in 0..2 {
+        lines.push(next_line(&mut file).await?);
+    }
+    assert_eq!(lines, vec![Some("Line 0".into()), Some("Line 1".into())]);
+    drop(file);
+
+    //a new file at the same path is read from the start.
+    scenario.delete().await?;
+    scenario.recreate().await?;
+    scenario.append(&numbered(1)).await?;
+    let file = scenario
+        .builder()
+        .start(StartPosition::Checkpoint(checkpoint))
+        .build()
+        .await?;
+    let mut file = BufReader::new(file).lines();
+    assert_eq!(next_line(&mut file).await?.as_deref(), Some("Line 0"));
+    Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn rewritten_in_place() -> Result<()> {
+    let scenario = Scenario::new("rewritten_in_place").await?;
+    scenario.append(&numbered(3)).await?;
+    let file = scenario.builder().compare_contents(true).build().await?;
+    let mut file = BufReader::new(file).lines();
+    let mut lines = Vec::new();
+    for _ in 0..3 {
+        lines.extend(next_line(&mut file).await?);
+    }
+    assert!(nothing_yet(&mut file).await);
+    //same inode, and longer than what we've read so far by the time we look.
+    scenario.truncate().await?;
+    scenario
+        .append("Other 0\nOther 1\nOther 2\nOther 3\n")
+        .await?;
+    for _ in 0..4 {
+        lines.extend(next_line(&mut file).await?);
+    }
+    scenario.delete().await?;
+    assert_eq!(next_line(&mut file).await?, None);
+    let expected = vec![
+        "Line 0", "Line 1", "Line 2", "Other 0", "Other 1", "Other 2", "Other 3",
+    ];
+    assert_eq!(lines, expected);
+    Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn write_after_delete() -> Result<()> {
+    let scenario = Scenario::new("write_after_delete").await?;
+    scenario.append(&numbered(2)).await?;
+    let mut writer = OpenOptions::new()
+        .append(true)
+        .open(scenario.path())
+        .await?;
+    let mut file = BufReader::new(scenario.builder().build().await?).lines();
+    let mut lines = Vec::new();
+    for _ in 0..2 {
+        lines.extend(next_line(&mut file).await?);
+    }
+    //the writer doesn't notice the delete and keeps going, that must not keep the stream open.
+    scenario.delete().await?;
+    writer.write_all(b"Line 2\n").await?;
+    writer.sync_data().await?;
+    while let Some(line) = next_line(&mut file).await? {
+        lines.push(line);
+    }
+    assert_eq!(lines, ["Line 0", "Line 1", "Line 2"]);
+    Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn line_stream() -> Result<()> {
+    use tokio::time::timeout;
+    let scenario = Scenario::new("line_stream").await?;
+    scenario.append(&numbered(2)).await?;
+    let mut lines = scenario.builder().build().await?.lines();
+    let mut read = Vec::new();
+    for _ in 0..2 {
+        read.extend(timeout(std::time::Duration::from_secs(5), lines.next()).await?);
+    }
+    scenario.rotate().await?;
+    scenario.append(&numbered(1)).await?;
+    read.extend(timeout(std::time::Duration::from_secs(5), lines.next()).await?);
+    scenario.delete().await?;
+    assert!(timeout(std::time::Duration::from_secs(5), lines.next())
+        .await?
+        .is_none());
+    let read = read
+        .into_iter()
+        .map(|line| {
+            let line = line.unwrap();
+            assert_eq!(&*line.path, scenario.path());
+            (
+                line.text,
+                line.byte_offset,
+                line.line_number,
+                line.generation,
+            )
+        })
+        .collect::<Vec<_>>();
+    let expected = vec![
+        ("Line 0".to_string(), 0, 1, 0),
+        ("Line 1".to_string(), 7, 2, 0),
+        ("Line 0".to_string(), 0, 1, 1),
+    ];
+    assert_eq!(read, expected);
+    Ok(())
+}
+
+//the next line and its offset, `None` if none comes within a second.
+async fn next_line_at(lines: &mut Lines) -> Option<(String, u64)> {
+    let line = tokio::time::timeout(std::time::Duration::from_secs(1), lines.next()).await;
+    line.ok().flatten().map(|line| {
+        let line = line.unwrap();
+        (line.text, line.byte_offset)
+    })
+}
+
+//the stream ended instead of staying pending.
+async fn ended(lines: &mut Lines) -> bool {
+    let next = tokio::time::timeout(std::time::Duration::from_secs(5), lines.next()).await;
+    matches!(next, Ok(None))
+}
+
+#[tokio::test(start_paused = true)]
+async fn partial_lines() -> Result<()> {
+    let scenario = Scenario::new("partial_lines").await?;
+    let mut lines = scenario.builder().build().await?.lines();
+    scenario.append("Line 0\nLi").await?;
+    assert_eq!(next_line_at(&mut lines).await, Some(("Line 0".into(), 0)));
+    //the rest of the line isn't there yet.
+    assert_eq!(next_line_at(&mut lines).await, None);
+    scenario.append("ne 1\nLast").await?;
+    assert_eq!(next_line_at(&mut lines).await, Some(("Line 1".into(), 7)));
+    assert_eq!(next_line_at(&mut lines).await, None);
+    //the last line never gets its newline.
+    scenario.delete().await?;
+    assert_eq!(next_line_at(&mut lines).await, Some(("Last".into(), 14)));
+    assert!(ended(&mut lines).await);
+    Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn flush_partial_after() -> Result<()> {
+    let scenario = Scenario::new("flush_partial_after").await?;
+    let mut lines = scenario
+        .builder()
+        .build()
+        .await?
+        .lines()
+        .flush_partial_after(std::time::Duration::from_millis(50));
+    scenario.append("Line 0\nLi").await?;
+    assert_eq!(next_line_at(&mut lines).await, Some(("Line 0".into(), 0)));
+    //nothing else shows up, so what's there of the line is handed out.
+    assert_eq!(next_line_at(&mut lines).await, Some(("Li".into(), 7)));
+    scenario.append("ne 1\n").await?;
+    assert_eq!(next_line_at(&mut lines).await, Some(("ne 1".into(), 9)));
+    scenario.delete().await?;
+    assert!(ended(&mut lines).await);
+    Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn stack_trace_records() -> Result<()> {
+    use tokio::time::timeout;
+    let scenario = Scenario::new("stack_trace_records").await?;
+    let continuation = regex::Regex::new(r"^\s|^Caused by:")?;
+    let mut records = scenario
+        .builder()
+        .build()
+        .await?
+        .lines()
+        .records(RecordBoundary::Continuation(continuation))
+        .max_lines(4);
+    async fn next(records: &mut Records) -> Option<(u64, String)> {
+        let record = timeout(std::time::Duration::from_secs(1), records.next()).await;
+        record.ok().flatten().map(|record| {
+            let record = record.unwrap();
+            (record.line_number, record.text)
+        })
+    }
+    scenario
+        .append(
+            "Exception in thread \"main\" java.lang.IllegalStateException\n\tat A.a(A.java:1)\n",
+        )
+        .await?;
+    //it's only over once a line that doesn't continue it shows up, or it's 4 lines long.
+    assert_eq!(next(&mut records).await, None);
+    scenario
+        .append("Caused by: java.lang.NullPointerException\n\tat B.b(B.java:2)\n")
+        .await?;
+    assert_eq!(
+        next(&mut records).await,
+        Some((1, "Exception in thread \"main\" java.lang.IllegalStateException\n\tat A.a(A.java:1)\nCaused by: java.lang.NullPointerException\n\tat B.b(B.java:2)".to_string()))
+    );
+    scenario.append("started\n").await?;
+    assert_eq!(next(&mut records).await, None);
+    scenario
+        .append("stopping\n\tat 1\n\tat 2\n\tat 3\n\tat 4\n")
+        .await?;
+    assert_eq!(next(&mut records).await, Some((5, "started".to_string())));
+    //cut off at 4 lines
+    assert_eq!(
+        next(&mut records).await,
+        Some((6, "stopping\n\tat 1\n\tat 2\n\tat 3".to_string()))
+    );
+    scenario.delete().await?;
+    assert_eq!(next(&mut records).await, Some((10, "\tat 4".to_string())));
+    assert!(timeout(std::time::Duration::from_secs(5), records.next())
+        .await?
+        .is_none());
+    Ok(())
+}
+
+#[tokio::test]
+async fn watched_glob() -> Result<()> {
+    use tokio::time::timeout;
+    let dir = "watched_glob";
+    let _ = tokio::fs::remove_dir_all(dir).await;
+    tokio::fs::create_dir(dir).await?;
+    write_test_file("watched_glob/a.log", false, 1).await?;
+    write_test_file("watched_glob/b.txt", false, 1).await?;
+    //directories that match aren't files we can read.
+    tokio::fs::create_dir("watched_glob/sub.log").await?;
+    let glob = WatchedGlob::new("watched_glob/*.log").await?;
+    let writer = tokio::spawn(async move {
         tokio::time::sleep(std::time::Duration::from_millis(100)).await;
-        tokio::fs::remove_file("simple_read").await?;
+        tokio::fs::create_dir("watched_glob/later.log").await?;
+        write_test_file("watched_glob/c.log", false, 2).await?;
+        write_test_file("watched_glob/b.txt", true, 1).await?;
         Ok::<(), Box<dyn std::error::Error + Send + Sync>>(())
     });
-    let file = WatchedFile::new("simple_read").await?;
-    let handle = tokio::spawn(async move {
-        let mut file = BufReader::new(file).lines();
-        let mut lines = Vec::new();
-        while let Some(line) = file.next_line().await.unwrap() {
-            lines.push(line);
-        }
-        return lines;
+    let mut lines = timeout(
+        std::time::Duration::from_secs(5),
+        glob.take(3)
+            .map(|line| {
+                let (path, line) = line.unwrap();
+                (path.to_str().unwrap().to_string(), line.text)
+            })
+            .collect::<Vec<_>>(),
+    )
+    .await?;
+    writer.await??;
+    tokio::fs::remove_dir_all(dir).await?;
+    lines.sort();
+    let expected = vec![
+        ("watched_glob/a.log".to_string(), "Line 0".to_string()),
+        ("watched_glob/c.log".to_string(), "Line 0".to_string()),
+        ("watched_glob/c.log".to_string(), "Line 1".to_string()),
+    ];
+    assert_eq!(lines, expected);
+    Ok(())
+}
+
+#[tokio::test]
+async fn watched_glob_rotation() -> Result<()> {
+    use tokio::time::timeout;
+    let dir = "watched_glob_rotation";
+    let _ = tokio::fs::remove_dir_all(dir).await;
+    tokio::fs::create_dir(dir).await?;
+    tokio::fs::write("watched_glob_rotation/app.log.1", "old\n").await?;
+    tokio::fs::write("watched_glob_rotation/app.log", "a\nb\n").await?;
+    //the rotated names match as well.
+    let mut glob = WatchedGlob::new("watched_glob_rotation/app.log*").await?;
+    let writer = tokio::spawn(async move {
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        tokio::fs::rename(
+            "watched_glob_rotation/app.log.1",
+            "watched_glob_rotation/app.log.2",
+        )
+        .await?;
+        tokio::fs::rename(
+            "watched_glob_rotation/app.log",
+            "watched_glob_rotation/app.log.1",
+        )
+        .await?;
+        tokio::fs::write("watched_glob_rotation/app.log", "c\n").await?;
+        Ok::<(), Box<dyn std::error::Error + Send + Sync>>(())
     });
-    let lines = handle.await?;
-    let expected = vec!["Line 0", "Line 1", "Line 2", "Line 3", "Line 4"];
+    let mut lines = timeout(
+        std::time::Duration::from_secs(5),
+        glob.by_ref()
+            .take(4)
+            .map(|line| {
+                let (path, line) = line.unwrap();
+                (path.to_str().unwrap().to_string(), line.text)
+            })
+            .collect::<Vec<_>>(),
+    )
+    .await?;
+    writer.await??;
+    //the rotated files aren't read again under their new names.
+    let more = timeout(std::time::Duration::from_millis(500), glob.next()).await;
+    tokio::fs::remove_dir_all(dir).await?;
+    assert!(more.is_err());
+    lines.sort();
+    let expected = vec![
+        ("watched_glob_rotation/app.log".to_string(), "a".to_string()),
+        ("watched_glob_rotation/app.log".to_string(), "b".to_string()),
+        ("watched_glob_rotation/app.log".to_string(), "c".to_string()),
+        (
+            "watched_glob_rotation/app.log.1".to_string(),
+            "old".to_string(),
+        ),
+    ];
     assert_eq!(lines, expected);
     Ok(())
 }
 
 #[tokio::test]
-async fn truncate() -> Result<()> {
-    touch("truncate").await?;
-    tokio::spawn(async move {
-        write_test_file("truncate", false, 3).await?;
-        write_test_file("truncate", false, 2).await?;
+async fn watched_dir() -> Result<()> {
+    use tokio::time::timeout;
+    let dir = "watched_dir";
+    let _ = tokio::fs::remove_dir_all(dir).await;
+    tokio::fs::create_dir_all("watched_dir/sub").await?;
+    write_test_file("watched_dir/a.log", false, 1).await?;
+    write_test_file("watched_dir/sub/b.log", false, 1).await?;
+    write_test_file("watched_dir/sub/skip.tmp", false, 1).await?;
+    let mut tree = WatchedDir::builder(dir)
+        .include("*.log*")
+        .exclude("archive/*")
+        .build()
+        .await?;
+    let writer = tokio::spawn(async move {
         tokio::time::sleep(std::time::Duration::from_millis(100)).await;
-        tokio::fs::remove_file("truncate").await?;
+        tokio::fs::create_dir_all("watched_dir/archive").await?;
+        write_test_file("watched_dir/archive/old.log", false, 1).await?;
+        //a new directory with a file in it right away.
+        tokio::fs::create_dir_all("watched_dir/new").await?;
+        write_test_file("watched_dir/new/c.log", false, 1).await?;
         Ok::<(), Box<dyn std::error::Error + Send + Sync>>(())
     });
-    let file = WatchedFile::new("truncate").await?;
-    let handle = tokio::spawn(async move {
-        let mut file = BufReader::new(file).lines();
-        let mut lines = Vec::new();
-        while let Some(line) = file.next_line().await.unwrap() {
-            lines.push(line);
-        }
-        return lines;
+    let mut lines = timeout(
+        std::time::Duration::from_secs(5),
+        tree.by_ref()
+            .take(3)
+            .map(|line| {
+                let (path, line) = line.unwrap();
+                (path.to_str().unwrap().to_string(), line.text)
+            })
+            .collect::<Vec<_>>(),
+    )
+    .await?;
+    writer.await??;
+    lines.sort();
+    let expected = vec![
+        ("watched_dir/a.log".to_string(), "Line 0".to_string()),
+        ("watched_dir/new/c.log".to_string(), "Line 0".to_string()),
+        ("watched_dir/sub/b.log".to_string(), "Line 0".to_string()),
+    ];
+    assert_eq!(lines, expected);
+    //rotating a.log away doesn't read it again as a.log.1, only the new a.log.
+    tokio::fs::rename("watched_dir/a.log", "watched_dir/a.log.1").await?;
+    write_test_file("watched_dir/a.log", false, 2).await?;
+    //but a file renamed to a name that's included is new to us, like an atomic write.
+    tokio::fs::rename("watched_dir/sub/skip.tmp", "watched_dir/sub/d.log").await?;
+    let mut lines = timeout(
+        std::time::Duration::from_secs(5),
+        tree.by_ref()
+            .take(3)
+            .map(|line| {
+                let (path, line) = line.unwrap();
+                (path.to_str().unwrap().to_string(), line.text)
+            })
+            .collect::<Vec<_>>(),
+    )
+    .await?;
+    let more = timeout(std::time::Duration::from_millis(500), tree.next()).await;
+    tokio::fs::remove_dir_all(dir).await?;
+    assert!(more.is_err());
+    lines.sort();
+    let expected = vec![
+        ("watched_dir/a.log".to_string(), "Line 0".to_string()),
+        ("watched_dir/a.log".to_string(), "Line 1".to_string()),
+        ("watched_dir/sub/d.log".to_string(), "Line 0".to_string()),
+    ];
+    assert_eq!(lines, expected);
+    Ok(())
+}
+
+#[tokio::test]
+async fn watch_hub() -> Result<()> {
+    use tokio::time::timeout;
+    let dir = "watch_hub";
+    let _ = tokio::fs::remove_dir_all(dir).await;
+    tokio::fs::create_dir(dir).await?;
+    touch("watch_hub/a.log").await?;
+    touch("watch_hub/b.log").await?;
+    let hub = WatchHub::new()?;
+    let mut a = WatchedFile::builder("watch_hub/a.log")
+        .hub(&hub)
+        .build()
+        .await?
+        .lines();
+    let mut b = WatchedFile::builder("watch_hub/b.log")
+        .hub(&hub)
+        .build()
+        .await?
+        .lines();
+    let writer = tokio::spawn(async move {
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        write_test_file("watch_hub/a.log", true, 1).await?;
+        write_test_file("watch_hub/b.log", true, 1).await?;
+        Ok::<(), Box<dyn std::error::Error + Send + Sync>>(())
     });
-    let lines = handle.await?;
-    let expected = vec!["Line 0", "Line 1", "Line 2", "Line 0", "Line 1"];
+    let line = timeout(std::time::Duration::from_secs(5), a.next()).await?;
+    assert_eq!(line.unwrap()?.text, "Line 0");
+    let line = timeout(std::time::Duration::from_secs(5), b.next()).await?;
+    assert_eq!(line.unwrap()?.text, "Line 0");
+    writer.await??;
+    //the directory is still watched for the other file.
+    drop(b);
+    let writer = tokio::spawn(async move {
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        write_test_file("watch_hub/a.log", true, 2).await?;
+        Ok::<(), Box<dyn std::error::Error + Send + Sync>>(())
+    });
+    let line = timeout(std::time::Duration::from_secs(5), a.next()).await?;
+    assert_eq!(line.unwrap()?.text, "Line 0");
+    writer.await??;
+    tokio::fs::remove_dir_all(dir).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn poll_backend() -> Result<()> {
+    use tokio::time::timeout;
+    let dir = "poll_backend";
+    let _ = tokio::fs::remove_dir_all(dir).await;
+    tokio::fs::create_dir(dir).await?;
+    let interval = std::time::Duration::from_millis(50);
+    for (i, backend) in [Backend::Poll(interval), Backend::Auto(interval)]
+        .into_iter()
+        .enumerate()
+    {
+        let path = format!("poll_backend/{}.log", i);
+        touch(&path).await?;
+        let file = WatchedFile::builder(&path).backend(backend).build().await?;
+        let writer = tokio::spawn(async move {
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            write_test_file(&path, true, 3).await?;
+            tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+            tokio::fs::remove_file(&path).await?;
+            Ok::<(), Box<dyn std::error::Error + Send + Sync>>(())
+        });
+        let lines = timeout(
+            std::time::Duration::from_secs(5),
+            file.lines()
+                .map(|line| line.unwrap().text)
+                .collect::<Vec<_>>(),
+        )
+        .await?;
+        writer.await??;
+        assert_eq!(lines, vec!["Line 0", "Line 1", "Line 2"], "{:?}", backend);
+    }
+    tokio::fs::remove_dir_all(dir).await?;
+    Ok(())
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn symlinked() -> Result<()> {
+    let dir = "symlinked";
+    let _ = tokio::fs::remove_dir_all(dir).await;
+    tokio::fs::create_dir_all("symlinked/a").await?;
+    tokio::fs::create_dir_all("symlinked/b").await?;
+    touch("symlinked/b/real.log").await?;
+    //like the links in `/var/log/containers`, writes go to the target.
+    tokio::fs::symlink("../b/real.log", "symlinked/a/link.log").await?;
+    let mut lines = BufReader::new(WatchedFile::new("symlinked/a/link.log").await?).lines();
+    //only the watcher can wake up a read that's already waiting.
+    let writer = tokio::spawn(async move {
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        write_test_file("symlinked/b/real.log", true, 1).await
+    });
+    assert_eq!(next_line(&mut lines).await?.as_deref(), Some("Line 0"));
+    writer.await??;
+    tokio::fs::remove_dir_all(dir).await?;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn native_watch_limit() -> Result<()> {
+    use tokio::time::timeout;
+    use tokio_watch::testing::{exhaust_native_watches, exhausted_hub};
+    let dir = "native_watch_limit";
+    let _ = tokio::fs::remove_dir_all(dir).await;
+    tokio::fs::create_dir(dir).await?;
+    for name in ["a", "b", "c", "d"] {
+        touch(&format!("native_watch_limit/{}.log", name)).await?;
+    }
+    let interval = std::time::Duration::from_millis(50);
+    let native = exhaust_native_watches(WatchedFile::builder("native_watch_limit/a.log"))
+        .backend(Backend::Native)
+        .build()
+        .await;
+    assert!(
+        matches!(&native, Err(Error::Watch(e)) if matches!(e.kind, notify::ErrorKind::MaxFilesWatch)),
+        "{:?}",
+        native.map(|_| ())
+    );
+    let mut a = exhaust_native_watches(WatchedFile::builder("native_watch_limit/a.log"))
+        .backend(Backend::Auto(interval))
+        .build()
+        .await?
+        .lines();
+    let hub = exhausted_hub(Backend::Auto(interval))?;
+    let mut b = WatchedFile::builder("native_watch_limit/b.log")
+        .hub(&hub)
+        .build()
+        .await?
+        .lines();
+    let mut c = WatchedFile::builder("native_watch_limit/c.log")
+        .hub(&hub)
+        .build()
+        .await?
+        .lines();
+    for (i, path) in ["a", "b", "c"].into_iter().enumerate() {
+        write_test_file(&format!("native_watch_limit/{}.log", path), true, i + 1).await?;
+    }
+    for (i, lines) in [&mut a, &mut b, &mut c].into_iter().enumerate() {
+        for n in 0..=i {
+            let line = timeout(std::time::Duration::from_secs(5), lines.next()).await?;
+            assert_eq!(line.unwrap()?.text, format!("Line {}", n));
+        }
+    }
+    //the polled directory is still watched for the other file.
+    drop(b);
+    write_test_file("native_watch_limit/c.log", true, 1).await?;
+    let line = timeout(std::time::Duration::from_secs(5), c.next()).await?;
+    assert_eq!(line.unwrap()?.text, "Line 0");
+    //after the last one left, watching the directory again polls it again.
+    drop(c);
+    let mut d = WatchedFile::builder("native_watch_limit/d.log")
+        .hub(&hub)
+        .build()
+        .await?
+        .lines();
+    write_test_file("native_watch_limit/d.log", true, 1).await?;
+    let line = timeout(std::time::Duration::from_secs(5), d.next()).await?;
+    assert_eq!(line.unwrap()?.text, "Line 0");
+    tokio::fs::remove_dir_all(dir).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn rotated_history() -> Result<()> {
+    use tokio::time::timeout;
+    let dir = "rotated_history";
+    let _ = tokio::fs::remove_dir_all(dir).await;
+    tokio::fs::create_dir(dir).await?;
+    write_test_file("rotated_history/app.log.2", false, 1).await?;
+    write_test_file("rotated_history/app.log.1", false, 2).await?;
+    write_test_file("rotated_history/app.log", false, 1).await?;
+    //not rotated copies we could read.
+    tokio::fs::write("rotated_history/app.log.3.bz2", b"BZh9").await?;
+    write_test_file("rotated_history/app.log.bak", false, 1).await?;
+    let file = WatchedFile::builder("rotated_history/app.log")
+        .read_rotated(true)
+        .build()
+        .await?;
+    let lines = timeout(
+        std::time::Duration::from_secs(5),
+        file.lines()
+            .take(4)
+            .map(|line| {
+                let line = line.unwrap();
+                let name = line.path.file_name().unwrap().to_str().unwrap().to_string();
+                (name, line.text, line.generation)
+            })
+            .collect::<Vec<_>>(),
+    )
+    .await?;
+    tokio::fs::remove_dir_all(dir).await?;
+    let expected = vec![
+        ("app.log.2".to_string(), "Line 0".to_string(), 0),
+        ("app.log.1".to_string(), "Line 0".to_string(), 1),
+        ("app.log.1".to_string(), "Line 1".to_string(), 1),
+        ("app.log".to_string(), "Line 0".to_string(), 2),
+    ];
     assert_eq!(lines, expected);
     Ok(())
 }
 
+#[cfg(feature = "compression")]
 #[tokio::test]
-async fn delete_while_read() -> Result<()> {
+async fn compressed_history() -> Result<()> {
+    use async_compression::tokio::write::{GzipEncoder, ZstdEncoder};
+    use tokio::time::timeout;
+    let dir = "compressed_history";
+    let _ = tokio::fs::remove_dir_all(dir).await;
+    tokio::fs::create_dir(dir).await?;
+    let mut gzip = GzipEncoder::new(Vec::new());
+    gzip.write_all(b"Line 0\nLine 1\n").await?;
+    gzip.shutdown().await?;
+    tokio::fs::write("compressed_history/app.log.2.gz", gzip.into_inner()).await?;
+    //told apart by its contents, not its name.
+    let mut zstd = ZstdEncoder::new(Vec::new());
+    zstd.write_all(b"Line 2\n").await?;
+    zstd.shutdown().await?;
+    tokio::fs::write("compressed_history/app.log.1", zstd.into_inner()).await?;
+    write_test_file("compressed_history/app.log", false, 1).await?;
+    let file = WatchedFile::builder("compressed_history/app.log")
+        .read_rotated(true)
+        .build()
+        .await?;
+    let lines = timeout(
+        std::time::Duration::from_secs(5),
+        file.lines()
+            .take(4)
+            .map(|line| {
+                let line = line.unwrap();
+                (line.text, line.byte_offset, line.generation)
+            })
+            .collect::<Vec<_>>(),
+    )
+    .await?;
+    tokio::fs::remove_dir_all(dir).await?;
+    let expected = vec![
+        ("Line 0".to_string(), 0, 0),
+        ("Line 1".to_string(), 7, 0),
+        ("Line 2".to_string(), 0, 1),
+        ("Line 0".to_string(), 0, 2),
+    ];
+    assert_eq!(lines, expected);
+    Ok(())
+}
+
+#[tokio::test]
+async fn no_follow() -> Result<()> {
     use tokio::time::timeout;
-    let filename = "delete_while_read";
-    // touch(file).await?;
+    write_test_file("no_follow", false, 3).await?;
+    let mut lines = WatchedFile::builder("no_follow")
+        .follow(false)
+        .build()
+        .await?
+        .lines();
+    let mut texts = Vec::new();
+    while let Some(line) = timeout(std::time::Duration::from_secs(5), lines.next()).await? {
+        texts.push(line?.text);
+    }
+    assert_eq!(texts, vec!["Line 0", "Line 1", "Line 2"]);
+    assert_eq!(lines.checkpoint().offset, 21);
+    tokio::fs::remove_file("no_follow").await?;
+    Ok(())
+}
+
+#[cfg(feature = "compression")]
+#[tokio::test]
+async fn no_follow_compressed() -> Result<()> {
+    use async_compression::tokio::write::GzipEncoder;
+    use tokio::time::timeout;
+    let filename = "no_follow_compressed.gz";
+    let mut gzip = GzipEncoder::new(Vec::new());
+    gzip.write_all(b"Line 0\nLine 1\n").await?;
+    gzip.shutdown().await?;
+    tokio::fs::write(filename, gzip.into_inner()).await?;
+    let mut lines = WatchedFile::builder(filename)
+        .follow(false)
+        .build()
+        .await?
+        .lines();
+    let mut read = Vec::new();
+    while let Some(line) = timeout(std::time::Duration::from_secs(5), lines.next()).await? {
+        let line = line?;
+        assert_eq!(&*line.path, Path::new(filename));
+        read.push((line.text, line.byte_offset));
+    }
+    assert_eq!(
+        read,
+        vec![("Line 0".to_string(), 0), ("Line 1".to_string(), 7)]
+    );
+    //where to start in the decompressed data can't be worked out from the compressed file.
+    let from_end = WatchedFile::builder(filename)
+        .start(StartPosition::End)
+        .follow(false)
+        .build()
+        .await;
+    assert!(
+        matches!(&from_end, Err(Error::Io(e)) if e.kind() == std::io::ErrorKind::InvalidInput),
+        "{:?}",
+        from_end.map(|_| ())
+    );
+    tokio::fs::remove_file(filename).await?;
+    Ok(())
+}
+
+#[cfg(not(feature = "compression"))]
+#[tokio::test]
+async fn no_follow_compressed() -> Result<()> {
+    use tokio::io::AsyncReadExt;
+    let filename = "no_follow_compressed.gz";
+    //the start of a gzip file, but without the feature it's just bytes, like with a `File`.
+    let bytes = [0x1f, 0x8b, 0x08, 0x00, b'\n', 0x00];
+    tokio::fs::write(filename, bytes).await?;
+    let mut file = WatchedFile::builder(filename).follow(false).build().await?;
+    let mut read = Vec::new();
+    file.read_to_end(&mut read).await?;
+    assert_eq!(read, bytes);
+    tokio::fs::remove_file(filename).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn start_last_bytes() -> Result<()> {
+    use tokio::time::timeout;
+    let filename = "start_last_bytes";
+    let read = |start| async move {
+        let lines = WatchedFile::builder(filename)
+            .start(start)
+            .follow(false)
+            .build()
+            .await?
+            .lines()
+            .map(|line| line.unwrap().text)
+            .collect::<Vec<_>>();
+        Ok::<_, Box<dyn std::error::Error + Send + Sync>>(
+            timeout(std::time::Duration::from_secs(5), lines).await?,
+        )
+    };
     write_test_file(filename, false, 5).await?;
-    let file = WatchedFile::new(filename).await?;
-    let handle = tokio::spawn(async move {
-        let mut file = BufReader::new(file).lines();
-        let mut lines = Vec::new();
-        while let Some(line) = file.next_line().await.unwrap() {
-            lines.push(line);
-            if lines.len() == 3 {
-                tokio::fs::remove_file(filename).await.unwrap();
+    //in the middle of "Line 3", which gets skipped.
+    assert_eq!(read(StartPosition::LastBytes(10)).await?, vec!["Line 4"]);
+    //right at the start of "Line 3".
+    assert_eq!(
+        read(StartPosition::LastBytes(14)).await?,
+        vec!["Line 3", "Line 4"]
+    );
+    assert_eq!(read(StartPosition::LastBytes(100)).await?.len(), 5);
+    //no line starts in the last bytes, the unfinished one they're part of is read whole.
+    tokio::fs::write(filename, "aaa\nbbbb").await?;
+    assert_eq!(read(StartPosition::LastBytes(2)).await?, vec!["bbbb"]);
+    tokio::fs::write(filename, "aaa\nbbbb\n").await?;
+    assert!(read(StartPosition::LastBytes(2)).await?.is_empty());
+    //more than one chunk has to be read backwards to find them.
+    write_test_file(filename, false, 2000).await?;
+    let lines = read(StartPosition::LastLines(1500)).await?;
+    assert_eq!(lines.len(), 1500);
+    assert_eq!(lines[0], "Line 500");
+    tokio::fs::remove_file(filename).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn seek_and_position() -> Result<()> {
+    use tokio::io::AsyncReadExt;
+    let filename = "seek_and_position";
+    write_test_file(filename, false, 3).await?;
+    let mut file = WatchedFile::builder(filename).follow(false).build().await?;
+    assert_eq!(file.path(), Path::new(filename));
+    assert_eq!(file.file_id().is_some(), cfg!(unix));
+    let mut text = String::new();
+    file.read_to_string(&mut text).await?;
+    assert_eq!(file.position(), 21);
+    //replay from the second line.
+    assert_eq!(file.seek(SeekFrom::Start(7)).await?, 7);
+    assert_eq!(file.position(), 7);
+    text.clear();
+    file.read_to_string(&mut text).await?;
+    assert_eq!(text, "Line 1\nLine 2\n");
+    assert_eq!(file.seek(SeekFrom::End(-7)).await?, 14);
+    //past the end would look like a truncation once we got there.
+    let mut events = file.events();
+    let past_end = file.seek(SeekFrom::End(100)).await.unwrap_err();
+    assert_eq!(past_end.kind(), std::io::ErrorKind::InvalidInput);
+    let before_start = file.seek(SeekFrom::Current(-100)).await.unwrap_err();
+    assert_eq!(before_start.kind(), std::io::ErrorKind::InvalidInput);
+    assert_eq!(file.position(), 14);
+    text.clear();
+    file.read_to_string(&mut text).await?;
+    assert_eq!(text, "Line 2\n");
+    assert!(events.try_recv().is_err());
+    //nor does it lose what was already buffered.
+    file.seek(SeekFrom::Start(0)).await?;
+    text.clear();
+    file.read_line(&mut text).await?;
+    assert!(file.seek(SeekFrom::Start(100)).await.is_err());
+    file.read_line(&mut text).await?;
+    assert_eq!(text, "Line 0\nLine 1\n");
+    tokio::fs::remove_file(filename).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn buf_read() -> Result<()> {
+    let dir = "buf_read";
+    let _ = tokio::fs::remove_dir_all(dir).await;
+    tokio::fs::create_dir(dir).await?;
+    write_test_file("buf_read/app.log.1", false, 2).await?;
+    write_test_file("buf_read/app.log", false, 2).await?;
+    let mut file = WatchedFile::builder("buf_read/app.log")
+        .read_rotated(true)
+        .follow(false)
+        .build()
+        .await?;
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    while file.read_line(&mut line).await? != 0 {
+        lines.push((line.trim_end().to_string(), file.generation()));
+        line.clear();
+    }
+    let expected = vec![
+        ("Line 0".to_string(), 0),
+        ("Line 1".to_string(), 0),
+        ("Line 0".to_string(), 1),
+        ("Line 1".to_string(), 1),
+    ];
+    assert_eq!(lines, expected);
+    //only what was handed out counts, not what sits in the buffer.
+    file.seek(SeekFrom::Start(0)).await?;
+    file.read_line(&mut line).await?;
+    assert_eq!(file.position(), 7);
+    assert_eq!(file.checkpoint().offset, 7);
+    assert_eq!(file.seek(SeekFrom::Current(0)).await?, 7);
+    line.clear();
+    file.read_line(&mut line).await?;
+    assert_eq!(line, "Line 1\n");
+    tokio::fs::remove_dir_all(dir).await?;
+    Ok(())
+}
+
+//the next line, failing the test instead of hanging if it never comes.
+async fn next_line<R: tokio::io::AsyncBufRead + Unpin>(
+    lines: &mut tokio::io::Lines<R>,
+) -> Result<Option<String>> {
+    Ok(tokio::time::timeout(std::time::Duration::from_secs(5), lines.next_line()).await??)
+}
+
+//with the clock paused this returns as soon as the file has nothing left to do.
+async fn nothing_yet<R: tokio::io::AsyncBufRead + Unpin>(lines: &mut tokio::io::Lines<R>) -> bool {
+    tokio::time::timeout(std::time::Duration::from_secs(1), lines.next_line())
+        .await
+        .is_err()
+}
+
+#[tokio::test(start_paused = true)]
+async fn scenario_append_and_truncate() -> Result<()> {
+    let scenario = Scenario::new("scenario_append_and_truncate").await?;
+    let mut lines = BufReader::new(scenario.builder().build().await?).lines();
+    assert!(nothing_yet(&mut lines).await);
+    scenario.append("one\ntwo\n").await?;
+    assert_eq!(next_line(&mut lines).await?.as_deref(), Some("one"));
+    assert_eq!(next_line(&mut lines).await?.as_deref(), Some("two"));
+    assert!(nothing_yet(&mut lines).await);
+    scenario.truncate().await?;
+    assert!(nothing_yet(&mut lines).await);
+    scenario.append("three\n").await?;
+    assert_eq!(next_line(&mut lines).await?.as_deref(), Some("three"));
+    Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn scenario_rotate() -> Result<()> {
+    let scenario = Scenario::new("scenario_rotate").await?;
+    let file = scenario.builder().build().await?;
+    let mut events = file.events();
+    let mut lines = BufReader::new(file).lines();
+    scenario.append("one\n").await?;
+    assert_eq!(next_line(&mut lines).await?.as_deref(), Some("one"));
+    scenario.rotate().await?;
+    scenario.append("two\n").await?;
+    assert_eq!(next_line(&mut lines).await?.as_deref(), Some("two"));
+    assert_eq!(events.try_recv()?, FileEvent::Rotated);
+    scenario.rotate().await?;
+    assert!(scenario.rotated(2).exists());
+    scenario.append("three\n").await?;
+    assert_eq!(next_line(&mut lines).await?.as_deref(), Some("three"));
+    assert!(nothing_yet(&mut lines).await);
+    Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn scenario_copy_truncate() -> Result<()> {
+    let scenario = Scenario::new("scenario_copy_truncate").await?;
+    let mut lines = BufReader::new(scenario.builder().build().await?).lines();
+    scenario.append("one\ntwo\n").await?;
+    assert_eq!(next_line(&mut lines).await?.as_deref(), Some("one"));
+    assert_eq!(next_line(&mut lines).await?.as_deref(), Some("two"));
+    scenario.copy_truncate().await?;
+    assert_eq!(tokio::fs::read(scenario.rotated(1)).await?, b"one\ntwo\n");
+    scenario.append("three\n").await?;
+    assert_eq!(next_line(&mut lines).await?.as_deref(), Some("three"));
+    assert!(nothing_yet(&mut lines).await);
+    Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn scenario_delete_and_recreate() -> Result<()> {
+    let scenario = Scenario::new("scenario_delete_and_recreate").await?;
+    let file = scenario
+        .builder()
+        .on_delete(DeletePolicy::WaitForRecreation)
+        .build()
+        .await?;
+    let mut lines = BufReader::new(file).lines();
+    scenario.append("one\n").await?;
+    assert_eq!(next_line(&mut lines).await?.as_deref(), Some("one"));
+    scenario.delete().await?;
+    assert!(nothing_yet(&mut lines).await);
+    scenario.recreate().await?;
+    scenario.append("two\n").await?;
+    assert_eq!(next_line(&mut lines).await?.as_deref(), Some("two"));
+    //without `WaitForRecreation` the file ends with the deletion.
+    let mut lines = BufReader::new(scenario.builder().build().await?).lines();
+    assert_eq!(next_line(&mut lines).await?.as_deref(), Some("two"));
+    scenario.delete().await?;
+    assert_eq!(next_line(&mut lines).await?, None);
+    Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn scenario_start_offset() -> Result<()> {
+    let scenario = Scenario::new("scenario_start_offset").await?;
+    scenario.append("one\ntwo\n").await?;
+    let from = |offset| scenario.builder().start(StartPosition::Offset(offset));
+    let mut lines = BufReader::new(from(4).build().await?).lines();
+    assert_eq!(next_line(&mut lines).await?.as_deref(), Some("two"));
+    let mut at_end = BufReader::new(from(8).build().await?).lines();
+    assert!(nothing_yet(&mut at_end).await);
+    scenario.append("three\n").await?;
+    assert_eq!(next_line(&mut at_end).await?.as_deref(), Some("three"));
+    assert_eq!(next_line(&mut lines).await?.as_deref(), Some("three"));
+    let past_end = from(100).build().await;
+    assert!(
+        matches!(&past_end, Err(Error::Io(e)) if e.kind() == std::io::ErrorKind::InvalidInput),
+        "{:?}",
+        past_end.map(|_| ())
+    );
+    Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn scenario_max_line_length() -> Result<()> {
+    use tokio::time::timeout;
+    let scenario = Scenario::new("scenario_max_line_length").await?;
+    let mut lines = scenario.builder().build().await?.lines().max_line_length(4);
+    async fn next(lines: &mut Lines) -> Option<(String, u64)> {
+        let line = timeout(std::time::Duration::from_secs(1), lines.next()).await;
+        line.ok().flatten().map(|line| {
+            let line = line.unwrap();
+            (line.text, line.byte_offset)
+        })
+    }
+    scenario.append("abcdefghij\nxyzw\n").await?;
+    assert_eq!(next(&mut lines).await, Some(("abcd".to_string(), 0)));
+    assert_eq!(next(&mut lines).await, Some(("efgh".to_string(), 4)));
+    assert_eq!(next(&mut lines).await, Some(("ij".to_string(), 8)));
+    assert_eq!(next(&mut lines).await, Some(("xyzw".to_string(), 11)));
+    //without a newline it doesn't have to wait for one.
+    scenario.append("0123456789").await?;
+    assert_eq!(next(&mut lines).await, Some(("0123".to_string(), 16)));
+    assert_eq!(next(&mut lines).await, Some(("4567".to_string(), 20)));
+    assert_eq!(next(&mut lines).await, None);
+    scenario.append("\n").await?;
+    assert_eq!(next(&mut lines).await, Some(("89".to_string(), 24)));
+    Ok(())
+}
+
+//the next record and the generation it's from, `None` if none comes within a second.
+async fn next_record(records: &mut Records) -> Option<(String, u64)> {
+    let record = tokio::time::timeout(std::time::Duration::from_secs(1), records.next()).await;
+    record.ok().flatten().map(|record| {
+        let record = record.unwrap();
+        (record.text, record.generation)
+    })
+}
+
+#[tokio::test(start_paused = true)]
+async fn scenario_record_start() -> Result<()> {
+    let scenario = Scenario::new("scenario_record_start").await?;
+    let start = regex::Regex::new(r"^\d{4}-\d{2}-\d{2} ")?;
+    let mut records = scenario
+        .builder()
+        .build()
+        .await?
+        .lines()
+        .records(RecordBoundary::Start(start))
+        .flush_after(None);
+    scenario
+        .append("2024-01-01 one\nwith more\n  and more\n2024-01-01 two\n")
+        .await?;
+    assert_eq!(
+        next_record(&mut records).await,
+        Some(("2024-01-01 one\nwith more\n  and more".to_string(), 0))
+    );
+    //without `flush_after` it's held back until the next one starts.
+    assert_eq!(next_record(&mut records).await, None);
+    scenario.append("still two\n2024-01-02 three\n").await?;
+    assert_eq!(
+        next_record(&mut records).await,
+        Some(("2024-01-01 two\nstill two".to_string(), 0))
+    );
+    Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn scenario_record_flush_after() -> Result<()> {
+    let scenario = Scenario::new("scenario_record_flush_after").await?;
+    let continuation = regex::Regex::new(r"^\s")?;
+    let flush_after = std::time::Duration::from_secs(3);
+    let mut records = scenario
+        .builder()
+        .build()
+        .await?
+        .lines()
+        .records(RecordBoundary::Continuation(continuation))
+        .flush_after(Some(flush_after));
+    scenario.append("one\n  a\n").await?;
+    let appended = tokio::time::Instant::now();
+    assert_eq!(next_record(&mut records).await, None);
+    //a continuation line coming in late still makes it.
+    scenario.append("  b\n").await?;
+    let extended = tokio::time::Instant::now();
+    let record = tokio::time::timeout(std::time::Duration::from_secs(5), records.next()).await?;
+    assert_eq!(record.unwrap()?.text, "one\n  a\n  b");
+    //counted from the last line, not the first one.
+    assert!(appended.elapsed() > flush_after);
+    assert!(extended.elapsed() >= flush_after);
+    Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn scenario_record_across_rotation() -> Result<()> {
+    let scenario = Scenario::new("scenario_record_across_rotation").await?;
+    let continuation = regex::Regex::new(r"^\s")?;
+    let mut records = scenario
+        .builder()
+        .build()
+        .await?
+        .lines()
+        .records(RecordBoundary::Continuation(continuation))
+        .flush_after(None);
+    scenario.append("one\n  a\n").await?;
+    assert_eq!(next_record(&mut records).await, None);
+    scenario.rotate().await?;
+    //what looks like the rest of it was written to another file, it's a record of its own.
+    scenario.append("  b\ntwo\n").await?;
+    assert_eq!(
+        next_record(&mut records).await,
+        Some(("one\n  a".to_string(), 0))
+    );
+    assert_eq!(
+        next_record(&mut records).await,
+        Some(("  b".to_string(), 1))
+    );
+    Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn scenario_debounce() -> Result<()> {
+    let scenario = Scenario::new("scenario_debounce").await?;
+    let debounce = std::time::Duration::from_secs(3);
+    let mut lines = BufReader::new(scenario.builder().debounce(debounce).build().await?).lines();
+    assert!(nothing_yet(&mut lines).await);
+    let woken = tokio::time::Instant::now();
+    scenario.append("one\n").await?;
+    assert!(nothing_yet(&mut lines).await);
+    //still within the debounce, read together with the first write.
+    scenario.append("two\n").await?;
+    assert_eq!(next_line(&mut lines).await?.as_deref(), Some("one"));
+    assert!(woken.elapsed() >= debounce);
+    let read = tokio::time::Instant::now();
+    assert_eq!(next_line(&mut lines).await?.as_deref(), Some("two"));
+    assert_eq!(read.elapsed(), std::time::Duration::ZERO);
+    Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn scenario_truncate_report() -> Result<()> {
+    let scenario = Scenario::new("scenario_truncate_report").await?;
+    let file = scenario
+        .builder()
+        .on_truncate(TruncatePolicy::Report)
+        .build()
+        .await?;
+    let mut events = file.events();
+    let mut lines = BufReader::new(file).lines();
+    scenario.append("one\ntwo\n").await?;
+    assert_eq!(next_line(&mut lines).await?.as_deref(), Some("one"));
+    assert_eq!(next_line(&mut lines).await?.as_deref(), Some("two"));
+    scenario.truncate().await?;
+    let e = lines.next_line().await.unwrap_err();
+    assert!(
+        matches!(
+            Error::from(e),
+            Error::Truncated {
+                old_len: 8,
+                new_len: 0
             }
+        ),
+        "expected a truncation"
+    );
+    assert_eq!(
+        events.try_recv()?,
+        FileEvent::Truncated {
+            old_len: 8,
+            new_len: 0
         }
-        return lines;
-    });
-    let lines = timeout(std::time::Duration::from_secs(5), handle).await??;
-    let expected = vec!["Line 0", "Line 1", "Line 2", "Line 3", "Line 4"];
-    assert_eq!(lines, expected);
+    );
+    //only the read that noticed it fails, then we start over.
+    scenario.append("three\n").await?;
+    assert_eq!(next_line(&mut lines).await?.as_deref(), Some("three"));
+    Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn scenario_delete_error() -> Result<()> {
+    let scenario = Scenario::new("scenario_delete_error").await?;
+    let file = scenario
+        .builder()
+        .on_delete(DeletePolicy::Error)
+        .build()
+        .await?;
+    let mut lines = BufReader::new(file).lines();
+    scenario.append("one\ntwo\n").await?;
+    assert_eq!(next_line(&mut lines).await?.as_deref(), Some("one"));
+    scenario.delete().await?;
+    //what was left in the file is still read first.
+    assert_eq!(next_line(&mut lines).await?.as_deref(), Some("two"));
+    let e = lines.next_line().await.unwrap_err();
+    assert!(matches!(Error::from(e), Error::FileVanished));
+    Ok(())
+}
+
+#[test]
+fn error_source_not_repeated() {
+    use std::error::Error as _;
+    let e = Error::from(std::io::Error::other("disk on fire"));
+    assert_eq!(e.to_string(), "io error");
+    assert_eq!(e.source().unwrap().to_string(), "disk on fire");
+    let e = Error::from(notify::Error::generic("queue overflow"));
+    assert_eq!(e.to_string(), "file watcher failed");
+    assert_eq!(e.source().unwrap().to_string(), "queue overflow");
+}
+
+//the link count is only there to check on unix.
+#[cfg(unix)]
+#[tokio::test(start_paused = true)]
+async fn unlinked_without_event() -> Result<()> {
+    use notify::event::{MetadataKind, ModifyKind};
+    use notify::{Event, EventKind};
+    let scenario = Scenario::new("unlinked_without_event").await?;
+    let mut lines = BufReader::new(scenario.builder().build().await?).lines();
+    let id = lines.get_ref().get_ref().file_id();
+    assert!(id.is_some());
+    for text in ["one", "two", "three"] {
+        scenario.append(&format!("{}\n", text)).await?;
+        assert_eq!(next_line(&mut lines).await?.as_deref(), Some(text));
+        assert!(nothing_yet(&mut lines).await);
+        //waking up at EOF checks the descriptor we have instead of opening the path again.
+        assert_eq!(lines.get_ref().get_ref().file_id(), id);
+    }
+    //the watcher misses the delete, only the link count dropping to zero gets reported.
+    tokio::fs::remove_file(scenario.path()).await?;
+    scenario.watcher().emit(
+        Event::new(EventKind::Modify(ModifyKind::Metadata(MetadataKind::Any)))
+            .add_path(scenario.path().to_path_buf()),
+    );
+    assert_eq!(next_line(&mut lines).await?, None);
+    Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn fake_watcher_unwatches_on_drop() -> Result<()> {
+    let scenario = Scenario::new("fake_watcher_unwatches_on_drop").await?;
+    let dir = scenario.path().parent().unwrap().to_path_buf();
+    let first = scenario.builder().build().await?;
+    let second = scenario.builder().build().await?;
+    assert!(scenario.watcher().is_watching(&dir));
+    drop(first);
+    assert!(scenario.watcher().is_watching(&dir));
+    drop(second);
+    assert!(!scenario.watcher().is_watching(&dir));
     Ok(())
 }
+
+#[test]
+fn unpin_and_send() {
+    //usable without pinning them first, and from spawned tasks.
+    fn assert_unpin_send<T: Unpin + Send>() {}
+    assert_unpin_send::<WatchedFile>();
+    assert_unpin_send::<Lines>();
+    assert_unpin_send::<Records>();
+    assert_unpin_send::<WatchedGlob>();
+    assert_unpin_send::<WatchedDir>();
+}